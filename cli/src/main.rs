@@ -1,6 +1,6 @@
 use lora_e5::{
-    process, AppEui, AppKey, Credentials, DevEui, LoraE5, CP210X_UART_BRIDGE_PID, DR,
-    SILICON_LABS_VID,
+    process, AbpCredentials, AppEui, AppKey, AppSKey, Class, Credentials, DevAddr, DevEui, LoraE5,
+    NwkSKey, Region, CP210X_UART_BRIDGE_PID, DR, SILICON_LABS_VID,
 };
 use std::str::FromStr;
 use thiserror::Error;
@@ -9,24 +9,62 @@ use tokio::time::Duration;
 #[derive(Debug, clap::Parser)]
 #[clap(version = env!("CARGO_PKG_VERSION"))]
 #[clap(name = "lora-e5-cli", about = "CLI for interacting with LoRa E5")]
+struct Cli {
+    /// Serial port of the modem (eg: /dev/ttyUSB1). Defaults to the first LoRa E5 found over USB.
+    #[arg(long, short, global = true)]
+    path: Option<String>,
+    /// Timeout of each read from the serial port, in milliseconds. Raise it if commands fail right
+    /// after the port is opened on a slow host.
+    #[arg(long, global = true, default_value = "10")]
+    serial_timeout: u64,
+    #[command(subcommand)]
+    cmd: Cmd,
+}
+
+#[derive(Debug, clap::Subcommand)]
 enum Cmd {
-    /// Send AT command to modem. Returns single-line response (does not work well with multi-line
-    /// responses, such as Join)
+    /// Send AT command to modem. Returns single-line response, unless --multiline is given
     At(At),
     /// Join. Use --force flag to force a join, otherwise active session will be maintained.
     Join(Join),
     /// Configure with credentials
     Configure(Configure),
+    /// Configure with ABP session credentials
+    ConfigureAbp(ConfigureAbp),
     /// Read out AppEui
     GetAppEui,
+    /// List connected LoRa E5 dev boards
+    Ports,
     /// Read out DevEui
     GetDevEui,
-    /// Set data rate
+    /// Read out DevAddr
+    GetDevAddr,
+    /// Read out data rate, or set it when a value is given
     Datarate(Datarate),
+    /// Set region (eg: US915, EU868, AU915)
+    Region(SetRegion),
+    /// Restrict a US915/AU915 modem to one subband of eight channels
+    Subband(Subband),
+    /// Read out TX power, or set it when a value is given
+    Power(Power),
+    /// Read out supply voltage
+    Vdd,
+    /// Read out internal temperature
+    Temp,
+    /// Read out version, region, mode, class, data rate, ADR and IDs in one go
+    Status,
+    /// Read out device class, or set it when a class (A, B, C) is given
+    Class(SetClass),
+    /// Read out adaptive data rate state, or enable/disable it with --on/--off
+    Adr(Adr),
+    /// Restart the modem
+    Reset(Reset),
     /// Send data. Input must be in hex format.
     Send(SendHex),
     /// Send ASCII
     SendAscii(SendAscii),
+    /// Run the AT commands in a file, one per line, stopping at the first failure
+    Script(Script),
 }
 
 #[derive(Debug, Clone, clap::Args)]
@@ -37,6 +75,39 @@ struct Configure {
     pub app_eui: AppEui,
     /// AppKey as hex string
     pub app_key: AppKey,
+    #[command(flatten)]
+    pub plan: ChannelPlan,
+}
+
+#[derive(Debug, Clone, clap::Args)]
+struct ChannelPlan {
+    /// Region (eg: US915, EU868, AU915)
+    #[arg(long, short, default_value = "US915")]
+    pub region: Region,
+    /// US915/AU915 subband (1-8). Defaults to 2 for US915.
+    #[arg(long, value_parser = clap::value_parser!(u8).range(1..=8))]
+    pub subband: Option<u8>,
+}
+
+impl ChannelPlan {
+    fn subband(&self) -> Option<u8> {
+        match (self.subband, self.region) {
+            (None, Region::Us915) => Some(2),
+            (subband, _) => subband,
+        }
+    }
+}
+
+#[derive(Debug, Clone, clap::Args)]
+struct ConfigureAbp {
+    /// DevAddr as hex string
+    pub dev_addr: DevAddr,
+    /// NwkSKey as hex string
+    pub nwk_skey: NwkSKey,
+    /// AppSKey as hex string
+    pub app_skey: AppSKey,
+    #[command(flatten)]
+    pub plan: ChannelPlan,
 }
 
 #[derive(Debug, Clone, clap::Args)]
@@ -49,11 +120,48 @@ struct SendHex {
     /// Require ACK
     #[arg(long, short)]
     pub confirmed: bool,
+    /// Time to wait for the uplink to complete, in millis
+    #[arg(long)]
+    pub timeout: Option<u64>,
 }
 
 #[derive(Debug, Clone, clap::Args)]
 struct Datarate {
-    pub dr: DR,
+    pub dr: Option<DR>,
+}
+
+#[derive(Debug, Clone, clap::Args)]
+struct SetRegion {
+    pub region: Region,
+}
+
+#[derive(Debug, Clone, clap::Args)]
+struct Subband {
+    /// Subband to use (1-8), matching the gateway's channel plan
+    #[arg(value_parser = clap::value_parser!(u8).range(1..=8))]
+    pub subband: u8,
+}
+
+#[derive(Debug, Clone, clap::Args)]
+struct Power {
+    /// TX power in dBm. The modem may clamp this to the region maximum.
+    pub dbm: Option<u8>,
+}
+
+#[derive(Debug, Clone, clap::Args)]
+struct SetClass {
+    /// Class B also needs beacon synchronisation, which is not managed by this tool
+    pub class: Option<Class>,
+}
+
+#[derive(Debug, Clone, clap::Args)]
+struct Adr {
+    /// Enable ADR
+    #[arg(long, conflicts_with = "off")]
+    pub on: bool,
+    /// Disable ADR
+    #[arg(long)]
+    pub off: bool,
 }
 
 #[derive(Debug, Clone, clap::Args)]
@@ -65,7 +173,7 @@ impl FromStr for HexData {
     type Err = hex::FromHexError;
 
     fn from_str(str: &str) -> std::result::Result<HexData, Self::Err> {
-        let data = hex::decode(str)?;
+        let data = hex::decode(lora_e5::normalize_hex(str))?;
         Ok(HexData { data })
     }
 }
@@ -79,15 +187,78 @@ struct SendAscii {
     /// Require ACK
     #[arg(long, short)]
     pub confirmed: bool,
+    /// Time to wait for the uplink to complete, in millis
+    #[arg(long)]
+    pub timeout: Option<u64>,
 }
 
 #[derive(Debug, clap::Args)]
 struct At {
     /// AT Command
     cmd: String,
-    /// Timeout in millis,
+    /// Timeout in millis. With --multiline, how long the modem must be quiet for the response to
+    /// be considered complete.
     #[arg(default_value = "250")]
     timeout: u64,
+    /// Collect every line of the response, such as an AT+CH dump
+    #[arg(long, short)]
+    multiline: bool,
+}
+
+#[derive(Debug, clap::Args)]
+struct Script {
+    /// File of AT commands. Blank lines and lines starting with # are skipped. Each response must
+    /// start with the command's name, eg: +DR: for AT+DR=EU868. Commands that answer otherwise,
+    /// such as ATZ, give the start of their response after a |, eg: ATZ|+VER:
+    file: std::path::PathBuf,
+}
+
+/// Pairs each command in a script with the prelude its response starts with: the one given after
+/// a `|`, taken as is, or else `+DR: ` for `AT+DR=EU868` and `+AT: ` for `AT`. Any other command
+/// without a prelude is rejected, since its response can't be predicted.
+fn parse_script(script: &str) -> Result<Vec<(String, String)>> {
+    script
+        .lines()
+        .enumerate()
+        .map(|(index, line)| (index + 1, line.trim_start()))
+        .filter(|(_, line)| !line.trim_end().is_empty() && !line.starts_with('#'))
+        .map(|(line, text)| {
+            let unknown = || Error::ScriptLine {
+                line,
+                text: text.trim_end().to_string(),
+            };
+            if let Some((cmd, prelude)) = text.split_once('|') {
+                let cmd = cmd.trim_end();
+                if cmd.is_empty() || prelude.is_empty() {
+                    return Err(unknown());
+                }
+                return Ok((cmd.to_string(), prelude.to_string()));
+            }
+            let cmd = text.trim_end();
+            let name = if cmd.eq_ignore_ascii_case("AT") {
+                "AT".to_string()
+            } else {
+                let name = cmd
+                    .get(..3)
+                    .filter(|prefix| prefix.eq_ignore_ascii_case("AT+"))
+                    .map(|_| &cmd[3..])
+                    .ok_or_else(unknown)?;
+                let name = name.split_once('=').map_or(name, |(name, _)| name);
+                if name.is_empty() {
+                    return Err(unknown());
+                }
+                name.to_ascii_uppercase()
+            };
+            Ok((cmd.to_string(), format!("+{name}: ")))
+        })
+        .collect()
+}
+
+#[derive(Debug, clap::Args)]
+struct Reset {
+    /// Use AT+RESET instead of ATZ
+    #[arg(long, short)]
+    soft: bool,
 }
 
 #[derive(Debug, clap::Args)]
@@ -95,6 +266,9 @@ struct Join {
     /// Force a join request. Otherwise, if device is already joined, no join occurs.
     #[arg(long, short)]
     force: bool,
+    /// Time to wait for the join to complete, in seconds
+    #[arg(long)]
+    timeout: Option<u64>,
 }
 
 pub type Result<T = ()> = std::result::Result<T, Error>;
@@ -102,40 +276,83 @@ pub type Result<T = ()> = std::result::Result<T, Error>;
 #[tokio::main(flavor = "current_thread")]
 async fn main() -> Result {
     use clap::Parser;
-    let cmd = Cmd::parse();
+    let Cli {
+        path,
+        serial_timeout,
+        cmd,
+    } = Cli::parse();
 
-    let process = process::Setup::default();
-    let client = process.get_client();
-    let runtime = process.complete();
-    let lora_e5 = LoraE5::<128>::open_usb(SILICON_LABS_VID, CP210X_UART_BRIDGE_PID)?;
+    if let Cmd::Ports = cmd {
+        for port in LoraE5::<128>::available_ports()? {
+            let serial_number = port.serial_number.as_deref().unwrap_or("-");
+            println!("{} {serial_number}", port.name);
+        }
+        return Ok(());
+    }
 
-    let runtime_handle = tokio::spawn(runtime.run(lora_e5));
+    let builder = LoraE5::<128>::builder().serial_timeout(Duration::from_millis(serial_timeout));
+    let lora_e5 = match path {
+        Some(path) => builder.open_path(path)?,
+        None => builder.open_usb(SILICON_LABS_VID, CP210X_UART_BRIDGE_PID)?,
+    };
+    let (client, runtime_handle) = process::spawn(lora_e5);
 
     match cmd {
-        Cmd::At(At { cmd, timeout }) => {
-            let response = client
-                .at_command(&cmd, Duration::from_millis(timeout))
-                .await?;
+        Cmd::At(At {
+            cmd,
+            timeout,
+            multiline,
+        }) => {
+            let timeout = Duration::from_millis(timeout);
+            let response = if multiline {
+                client.at_command_multiline(&cmd, timeout).await?
+            } else {
+                client.at_command(&cmd, timeout).await?
+            };
             println!("{response}");
         }
-        Cmd::Join(Join { force }) => {
-            let join_response = client.join(force).await?;
+        Cmd::Join(Join { force, timeout }) => {
+            let join_response = match timeout {
+                Some(timeout) => {
+                    let timeout = Duration::from_secs(timeout);
+                    client.join_with_timeout(force, timeout).await?
+                }
+                None => client.join(force).await?,
+            };
             println!("{join_response:?}");
         }
         Cmd::Configure(Configure {
             dev_eui,
             app_eui,
             app_key,
+            plan,
         }) => {
+            let credentials = Credentials {
+                dev_eui,
+                app_eui,
+                app_key,
+            };
             client
-                .configure(Credentials {
-                    dev_eui,
-                    app_eui,
-                    app_key,
-                })
+                .configure(credentials, plan.region, plan.subband())
                 .await?;
             println!("Credentials configured");
         }
+        Cmd::ConfigureAbp(ConfigureAbp {
+            dev_addr,
+            nwk_skey,
+            app_skey,
+            plan,
+        }) => {
+            let credentials = AbpCredentials {
+                dev_addr,
+                nwk_skey,
+                app_skey,
+            };
+            client
+                .configure(credentials, plan.region, plan.subband())
+                .await?;
+            println!("ABP credentials configured");
+        }
         Cmd::GetAppEui => {
             let app_eui = client.get_app_eui().await?.to_string();
             println!("{app_eui}");
@@ -144,26 +361,124 @@ async fn main() -> Result {
             let dev_eui = client.get_dev_eui().await?.to_string();
             println!("{dev_eui}");
         }
-        Cmd::Datarate(Datarate { dr }) => {
+        Cmd::Ports => unreachable!("handled before opening the modem"),
+        Cmd::GetDevAddr => {
+            let dev_addr = client.get_dev_addr().await?.to_string();
+            println!("{dev_addr}");
+        }
+        Cmd::Datarate(Datarate { dr: Some(dr) }) => {
             client.data_rate(dr).await?;
             println!("DR{} set", dr.as_str());
         }
+        Cmd::Datarate(Datarate { dr: None }) => {
+            let dr = client.get_data_rate().await?;
+            println!("DR{}", dr.as_str());
+        }
+        Cmd::Region(SetRegion { region }) => {
+            client.region(region).await?;
+            println!("{} set", region.as_str());
+        }
+        Cmd::Subband(Subband { subband }) => {
+            client.subband(subband).await?;
+            println!("Subband {subband} set");
+        }
+        Cmd::Power(Power { dbm }) => {
+            let dbm = match dbm {
+                Some(dbm) => client.tx_power(dbm).await?,
+                None => client.get_tx_power().await?,
+            };
+            println!("{dbm} dBm");
+        }
+        Cmd::Vdd => {
+            let vdd = client.get_vdd().await?;
+            println!("{vdd:.2} V");
+        }
+        Cmd::Temp => {
+            let celsius = client.get_temperature().await?;
+            println!("{celsius:.1} °C");
+        }
+        Cmd::Status => {
+            let status = client.status().await?;
+            let fields = [
+                ("Version", status.version.map(|v| v.to_string())),
+                ("Region", status.region.map(|r| r.as_str().to_string())),
+                ("Mode", status.mode.map(|m| m.as_str().to_string())),
+                ("Class", status.class.map(|c| c.as_str().to_string())),
+                (
+                    "Datarate",
+                    status.datarate.map(|dr| format!("DR{}", dr.as_str())),
+                ),
+                (
+                    "ADR",
+                    status.adr.map(|on| if on { "on" } else { "off" }.into()),
+                ),
+                ("DevEui", status.dev_eui.map(|id| id.to_string())),
+                ("AppEui", status.app_eui.map(|id| id.to_string())),
+                ("DevAddr", status.dev_addr.map(|id| id.to_string())),
+            ];
+            for (name, value) in fields {
+                println!("{name:<9} {}", value.as_deref().unwrap_or("-"));
+            }
+        }
+        Cmd::Class(SetClass { class }) => {
+            if let Some(class) = class {
+                client.class(class).await?;
+            }
+            let class = client.get_class().await?;
+            println!("Class {}", class.as_str());
+        }
+        Cmd::Adr(Adr { on, off }) => {
+            if on || off {
+                client.adr(on).await?;
+            }
+            let enabled = client.get_adr().await?;
+            println!("ADR {}", if enabled { "on" } else { "off" });
+        }
+        Cmd::Reset(Reset { soft }) => {
+            client.reset(soft).await?;
+            println!("Modem reset");
+        }
         Cmd::Send(SendHex {
             data,
             port,
             confirmed,
+            timeout,
         }) => {
-            let response = client.send(data.data, port, confirmed).await?;
+            let response = match timeout {
+                Some(timeout) => {
+                    let timeout = Duration::from_millis(timeout);
+                    client
+                        .send_with_timeout(data.data, port, confirmed, timeout)
+                        .await?
+                }
+                None => client.send(data.data, port, confirmed).await?,
+            };
             println!("{response:?}");
         }
         Cmd::SendAscii(SendAscii {
             data,
             port,
             confirmed,
+            timeout,
         }) => {
-            let response = client.send_ascii(data, port, confirmed).await?;
+            let response = match timeout {
+                Some(timeout) => {
+                    let timeout = Duration::from_millis(timeout);
+                    client
+                        .send_ascii_with_timeout(data, port, confirmed, timeout)
+                        .await?
+                }
+                None => client.send_ascii(data, port, confirmed).await?,
+            };
             println!("{response:?}");
         }
+        Cmd::Script(Script { file }) => {
+            let commands = parse_script(&std::fs::read_to_string(file)?)?;
+            let responses = client.run_script(commands.clone()).await?;
+            for ((cmd, _), response) in commands.iter().zip(responses) {
+                println!("{cmd} -> {response}");
+            }
+        }
     }
 
     client.send_shutdown().await?;
@@ -180,4 +495,8 @@ pub enum Error {
     LoraE5Process(#[from] lora_e5::process::Error),
     #[error("join handle error: {0}")]
     JoinHandle(#[from] tokio::task::JoinError),
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("script line {line}: no prelude for {text:?}; give one after a |")]
+    ScriptLine { line: usize, text: String },
 }
@@ -0,0 +1,309 @@
+use super::*;
+
+/// Configures how a `LoraE5` is opened.
+///
+/// The serial timeout bounds each individual read from the port, while the command timeout bounds
+/// how long a command waits for the modem to go quiet before giving up on its response.
+#[derive(Debug, Clone)]
+pub struct LoraE5Builder<const N: usize> {
+    baud: u32,
+    #[cfg(feature = "serialport")]
+    framing: Framing,
+    serial_timeout: Duration,
+    command_timeout: Duration,
+    sleep_on_close: bool,
+    query_attempts: u8,
+    command_delay: Duration,
+    require_joined: bool,
+    line_ending: &'static str,
+    cache_ids: bool,
+    probe: bool,
+}
+
+impl<const N: usize> Default for LoraE5Builder<N> {
+    fn default() -> Self {
+        Self {
+            baud: DEFAULT_BAUD,
+            #[cfg(feature = "serialport")]
+            framing: Framing::default(),
+            serial_timeout: DEFAULT_SERIAL_TIMEOUT,
+            command_timeout: DEFAULT_TIMEOUT,
+            sleep_on_close: false,
+            query_attempts: 1,
+            command_delay: Duration::ZERO,
+            require_joined: false,
+            line_ending: "\n",
+            cache_ids: false,
+            probe: false,
+        }
+    }
+}
+
+impl<const N: usize> LoraE5Builder<N> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn baud(mut self, baud: u32) -> Self {
+        self.baud = baud;
+        self
+    }
+
+    /// Sets the flow control of ports the builder opens. The default is none, which is how the
+    /// LoRa E5 dev board is wired; hardware flow control needs RTS and CTS connected.
+    #[cfg(feature = "serialport")]
+    pub fn flow_control(mut self, flow_control: serialport::FlowControl) -> Self {
+        self.framing.flow_control = flow_control;
+        self
+    }
+
+    /// Sets the data bits, parity and stop bits of ports the builder opens, which default to the
+    /// modem's 8N1
+    #[cfg(feature = "serialport")]
+    pub fn framing(
+        mut self,
+        data_bits: serialport::DataBits,
+        parity: serialport::Parity,
+        stop_bits: serialport::StopBits,
+    ) -> Self {
+        self.framing.data_bits = data_bits;
+        self.framing.parity = parity;
+        self.framing.stop_bits = stop_bits;
+        self
+    }
+
+    /// Overrides the default 10ms timeout of each read from a port the builder opens. This is the
+    /// longest a single read blocks waiting for bytes, not how long a command waits for its
+    /// response, which `default_command_timeout` sets; reads are repeated until the command
+    /// completes or times out. Raise it on slow or virtualized hosts where reads right after
+    /// opening the port fail with `Error::PartialResponse`.
+    pub fn serial_timeout(mut self, timeout: Duration) -> Self {
+        self.serial_timeout = timeout;
+        self
+    }
+
+    /// Overrides the default 5 second timeout used by commands that don't set their own. This
+    /// bounds a whole command, across however many serial reads its response takes.
+    pub fn default_command_timeout(mut self, timeout: Duration) -> Self {
+        self.command_timeout = timeout;
+        self
+    }
+
+    /// Puts the modem into low power mode when the `LoraE5` is closed or dropped, so it isn't left
+    /// mid-transaction between process restarts
+    pub fn sleep_on_close(mut self, sleep: bool) -> Self {
+        self.sleep_on_close = sleep;
+        self
+    }
+
+    /// How many times queries such as `get_version` or `get_dev_eui` are sent before a timed out
+    /// or failed read is returned as an error. Defaults to 1, which never retries; 0 is treated
+    /// the same.
+    pub fn query_attempts(mut self, attempts: u8) -> Self {
+        self.query_attempts = attempts;
+        self
+    }
+
+    /// Waits this long before writing each command, giving firmware that garbles back-to-back
+    /// commands time to settle. Defaults to no delay; 20ms is usually enough.
+    pub fn command_delay(mut self, delay: Duration) -> Self {
+        self.command_delay = delay;
+        self
+    }
+
+    /// Makes `send` and `send_ascii` fail with `Error::NotJoined` before writing anything unless
+    /// `join` or `force_join` has succeeded through this handle. Only suits OTAA, as ABP sessions
+    /// never join.
+    pub fn require_joined(mut self, require: bool) -> Self {
+        self.require_joined = require;
+        self
+    }
+
+    /// Sets what is written after each command. Defaults to `"\n"`, which the modem accepts;
+    /// use `"\r\n"` for serial stacks that hold back a line until they see a carriage return.
+    pub fn line_ending(mut self, ending: &'static str) -> Self {
+        self.line_ending = ending;
+        self
+    }
+
+    /// Keeps the DevEui and AppEui once read or set, so `get_dev_eui` and `get_app_eui` only
+    /// query the modem the first time. Off by default; `LoraE5::refresh_ids` clears the cache.
+    pub fn cache_ids(mut self, cache: bool) -> Self {
+        self.cache_ids = cache;
+        self
+    }
+
+    /// Checks that the modem answers `AT` right after the builder opens its port, with
+    /// `LoraE5::probe`, so that an unresponsive modem fails the open with `Error::Unresponsive`.
+    /// Off by default. Applies to transports passed to `with_transport` as well.
+    pub fn probe(mut self, probe: bool) -> Self {
+        self.probe = probe;
+        self
+    }
+
+    /// Builds a `LoraE5` around an already open transport, probing it first if `probe` is set.
+    /// Serial settings such as the baud rate only apply to ports opened by the builder.
+    pub fn with_transport<T: Transport>(self, transport: T) -> Result<LoraE5<N, T>> {
+        let probe = self.probe;
+        let mut lora_e5 = self.build(transport);
+        if probe {
+            lora_e5.probe()?;
+        }
+        Ok(lora_e5)
+    }
+
+    /// Wraps `transport` with the builder's settings, without probing it
+    pub(crate) fn build<T: Transport>(self, transport: T) -> LoraE5<N, T> {
+        let mut lora_e5 = LoraE5::new(transport, self.command_timeout);
+        lora_e5.sleep_on_close = self.sleep_on_close;
+        lora_e5.query_attempts = self.query_attempts;
+        lora_e5.command_delay = self.command_delay;
+        lora_e5.require_joined = self.require_joined;
+        lora_e5.line_ending = self.line_ending;
+        lora_e5.cache_ids = self.cache_ids;
+        lora_e5
+    }
+
+    #[cfg(feature = "serialport")]
+    pub fn open_usb(self, vid: u16, pid: u16) -> Result<LoraE5<N>> {
+        self.open_with(
+            move |builder| match usb_ports(vid, pid)?.into_iter().next() {
+                Some(port) => builder.open_port(&port.name),
+                None => Err(Error::PortNotFound { vid, pid }),
+            },
+        )
+    }
+
+    /// Opens the LoRa E5 whose USB serial number is `serial`, for picking one of several dongles
+    #[cfg(feature = "serialport")]
+    pub fn open_by_serial(self, serial: &str) -> Result<LoraE5<N>> {
+        let serial = serial.to_string();
+        self.open_with(move |builder| {
+            let port = usb_ports(SILICON_LABS_VID, CP210X_UART_BRIDGE_PID)?
+                .into_iter()
+                .find(|port| port.serial_number.as_ref() == Some(&serial))
+                .ok_or_else(|| Error::SerialNotFound(serial.clone()))?;
+            builder.open_port(&port.name)
+        })
+    }
+
+    #[cfg(feature = "serialport")]
+    pub fn open_path<'a>(self, path: impl Into<std::borrow::Cow<'a, str>>) -> Result<LoraE5<N>> {
+        let path = path.into().into_owned();
+        self.open_with(move |builder| builder.open_port(&path))
+    }
+
+    /// Opens the port with `open`, keeping it so that `LoraE5::reconnect` can open it again
+    #[cfg(feature = "serialport")]
+    fn open_with(
+        self,
+        open: impl Fn(&Self) -> Result<DefaultTransport> + Send + 'static,
+    ) -> Result<LoraE5<N>> {
+        let port = open(&self)?;
+        let mut lora_e5 = self.clone().with_transport(port)?;
+        lora_e5.reopen = Some(Box::new(move || open(&self)));
+        Ok(lora_e5)
+    }
+
+    #[cfg(feature = "serialport")]
+    fn open_port(&self, path: &str) -> Result<DefaultTransport> {
+        #[cfg(windows)]
+        let path = &*device_path(path);
+        Ok(serialport::new(path, self.baud)
+            .data_bits(self.framing.data_bits)
+            .parity(self.framing.parity)
+            .stop_bits(self.framing.stop_bits)
+            .flow_control(self.framing.flow_control)
+            .timeout(self.serial_timeout)
+            .open()?)
+    }
+}
+
+/// Serial line settings other than the baud rate
+#[cfg(feature = "serialport")]
+#[derive(Debug, Clone, Copy)]
+struct Framing {
+    data_bits: serialport::DataBits,
+    parity: serialport::Parity,
+    stop_bits: serialport::StopBits,
+    flow_control: serialport::FlowControl,
+}
+
+#[cfg(feature = "serialport")]
+impl Default for Framing {
+    fn default() -> Self {
+        Self {
+            data_bits: serialport::DataBits::Eight,
+            parity: serialport::Parity::None,
+            stop_bits: serialport::StopBits::One,
+            flow_control: serialport::FlowControl::None,
+        }
+    }
+}
+
+/// A serial port that may have a LoRa E5 attached, as listed by `LoraE5::available_ports`
+#[cfg(feature = "serialport")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PortInfo {
+    /// Path to open the port with, such as `/dev/ttyUSB0` or `COM3`
+    pub name: String,
+    pub vid: u16,
+    pub pid: u16,
+    pub serial_number: Option<String>,
+}
+
+#[cfg(feature = "serialport")]
+pub(crate) fn usb_ports(vid: u16, pid: u16) -> Result<Vec<PortInfo>> {
+    Ok(serialport::available_ports()?
+        .into_iter()
+        .filter_map(|port| usb_port(port, vid, pid, cfg!(windows)))
+        .collect())
+}
+
+/// Driver name Windows includes in the friendly name of CP210x ports, such as
+/// `Silicon Labs CP210x USB to UART Bridge (COM3)`
+#[cfg(feature = "serialport")]
+const CP210X_FRIENDLY_NAME: &str = "CP210x";
+
+/// Matches a listed port against `vid` and `pid`. Windows sometimes reports incomplete USB ids
+/// for a port, so with `match_friendly_name` a CP210x port is also recognised by the driver's
+/// friendly name, which Windows reports as the product.
+#[cfg(feature = "serialport")]
+pub(crate) fn usb_port(
+    port: serialport::SerialPortInfo,
+    vid: u16,
+    pid: u16,
+    match_friendly_name: bool,
+) -> Option<PortInfo> {
+    let serialport::SerialPortType::UsbPort(usb_port) = port.port_type else {
+        return None;
+    };
+    let ids_match = usb_port.vid == vid && usb_port.pid == pid;
+    let friendly_name_matches = match_friendly_name
+        && (vid, pid) == (SILICON_LABS_VID, CP210X_UART_BRIDGE_PID)
+        && usb_port
+            .product
+            .as_deref()
+            .is_some_and(|product| product.contains(CP210X_FRIENDLY_NAME));
+    (ids_match || friendly_name_matches).then_some(PortInfo {
+        name: port.port_name,
+        vid: usb_port.vid,
+        pid: usb_port.pid,
+        serial_number: usb_port.serial_number,
+    })
+}
+
+/// Windows only opens `COM1`-`COM9` by their bare names, so COM ports are opened through the
+/// `\\.\` device namespace, which works for any number. Other paths, including ones already
+/// in the device namespace, are left alone.
+#[cfg(all(feature = "serialport", any(windows, test)))]
+pub(crate) fn device_path(path: &str) -> std::borrow::Cow<'_, str> {
+    let is_com_port = path
+        .get(..3)
+        .is_some_and(|prefix| prefix.eq_ignore_ascii_case("COM"));
+    if is_com_port {
+        format!(r"\\.\{path}").into()
+    } else {
+        path.into()
+    }
+}
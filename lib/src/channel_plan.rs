@@ -0,0 +1,151 @@
+use super::*;
+
+/// One channel of the modem's frequency plan
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Channel {
+    pub index: u8,
+    pub freq_hz: u32,
+    pub enabled: bool,
+    pub dr_min: DR,
+    pub dr_max: DR,
+}
+
+impl<const N: usize, T: Transport> LoraE5<N, T> {
+    /// Reads the channels the modem may transmit on from the `AT+CH` dump. The dump lists only
+    /// the enabled channels, so for US915 and AU915, once the region is known through
+    /// `set_region` or `get_region`, the disabled channels of the fixed 72 channel plan are filled
+    /// in too. In other regions, or with the region unknown, only the enabled channels are
+    /// returned. Like `get_channels`, `N` must be large enough to hold the dump.
+    pub fn get_channel_plan(&mut self) -> Result<Vec<Channel>> {
+        const EXPECTED_PRELUDE: &str = "+CH: ";
+        let n = self.query("AT+CH")?;
+        let response = self.framed_response(n, EXPECTED_PRELUDE)?;
+        let enabled = parse_channel_plan(response)?;
+        let Some(region @ (Region::Us915 | Region::Au915)) = self.region else {
+            return Ok(enabled);
+        };
+        Ok((0..72)
+            .map(|index| {
+                enabled
+                    .iter()
+                    .find(|channel| channel.index == index)
+                    .copied()
+                    .unwrap_or_else(|| fixed_plan_channel(region, index))
+            })
+            .collect())
+    }
+
+    /// Defines channel `index` with its frequency and data rate range using
+    /// `AT+CH=<index>, <MHz>, DR<min>, DR<max>`, for private networks with their own channel
+    /// plan. Use `set_channel` to only turn an existing channel on or off.
+    pub fn define_channel(&mut self, index: u8, freq_hz: u32, dr_min: DR, dr_max: DR) -> Result {
+        const EXPECTED_PRELUDE: &str = "+CH: ";
+        if dr_min > dr_max {
+            return Err(Error::InvalidDatarateRange {
+                min: dr_min,
+                max: dr_max,
+            });
+        }
+        let cmd = format!(
+            "AT+CH={index}, {}, DR{}, DR{}",
+            format_mhz(freq_hz),
+            dr_min.as_str(),
+            dr_max.as_str()
+        );
+        self.write_command(&cmd)?;
+        let n = self.read_until_break(self.command_timeout)?;
+        let response = self.framed_response(n, EXPECTED_PRELUDE)?;
+        let channel = Channel {
+            index,
+            freq_hz,
+            enabled: true,
+            dr_min,
+            dr_max,
+        };
+        if echoes_channel(response, &channel) {
+            Ok(())
+        } else {
+            Err(Error::unexpected_framed(
+                EXPECTED_PRELUDE,
+                format_args!(
+                    "{index}, {freq_hz}, DR{} ~ DR{}",
+                    dr_min.as_str(),
+                    dr_max.as_str()
+                ),
+                response,
+            ))
+        }
+    }
+}
+
+/// Checks the echo of a channel definition, such as
+/// `3,868700000,DR0:SF12 BW125KHZ ~ DR5:SF7 BW125KHZ`, against the channel that was defined.
+/// Extra spacing and a `CH` prefix on the index are tolerated.
+fn echoes_channel(response: &str, channel: &Channel) -> bool {
+    let response = response.trim_end();
+    let response = response.strip_prefix("CH").unwrap_or(response);
+    let mut fields = response.splitn(3, ',').map(str::trim);
+    let index = fields.next().and_then(|index| index.parse::<u8>().ok());
+    let freq_hz = fields.next().and_then(|freq| freq.parse::<u32>().ok());
+    let drs: Option<Vec<DR>> = fields.next().and_then(|drs| {
+        drs.split('~')
+            .map(|dr| {
+                let dr = dr.trim().split([':', ' ']).next()?;
+                DR::from_str(dr).ok()
+            })
+            .collect()
+    });
+    index == Some(channel.index)
+        && freq_hz == Some(channel.freq_hz)
+        && drs == Some(vec![channel.dr_min, channel.dr_max])
+}
+
+/// Parses an `AT+CH` dump such as `3; 0,868100000,DR0,DR5; 1,868300000,DR0,DR5; ...` into its
+/// channels, all of which are enabled
+pub(crate) fn parse_channel_plan(response: &str) -> Result<Vec<Channel>> {
+    let unexpected = || Error::unexpected("<index>,<frequency>,<DR min>,<DR max>", response);
+    response
+        .trim_end()
+        .split(';')
+        .skip(1)
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| {
+            let fields: Vec<&str> = entry.split(',').map(str::trim).collect();
+            let [index, freq_hz, dr_min, dr_max] = fields[..] else {
+                return Err(unexpected());
+            };
+            Ok(Channel {
+                index: index.parse().map_err(ParseError::from)?,
+                freq_hz: freq_hz.parse().map_err(ParseError::from)?,
+                enabled: true,
+                dr_min: DR::from_str(dr_min)?,
+                dr_max: DR::from_str(dr_max)?,
+            })
+        })
+        .collect()
+}
+
+/// A disabled channel of the US915 or AU915 plan: 64 125kHz channels spaced 200kHz apart,
+/// followed by 8 500kHz channels spaced 1.6MHz apart
+fn fixed_plan_channel(region: Region, index: u8) -> Channel {
+    let (narrow_base, wide_base, narrow_drs, wide_dr) = match region {
+        Region::Au915 => (915_200_000, 915_900_000, (DR::_0, DR::_5), DR::_6),
+        _ => (902_300_000, 903_000_000, (DR::_0, DR::_3), DR::_4),
+    };
+    let (freq_hz, (dr_min, dr_max)) = if index < 64 {
+        (narrow_base + u32::from(index) * 200_000, narrow_drs)
+    } else {
+        (
+            wide_base + u32::from(index - 64) * 1_600_000,
+            (wide_dr, wide_dr),
+        )
+    };
+    Channel {
+        index,
+        freq_hz,
+        enabled: false,
+        dr_min,
+        dr_max,
+    }
+}
@@ -1,6 +1,7 @@
 use std::{fmt, str::FromStr};
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Credentials {
     pub app_eui: AppEui,
     pub app_key: AppKey,
@@ -17,18 +18,159 @@ impl Credentials {
     }
 }
 
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AbpCredentials {
+    pub dev_addr: DevAddr,
+    pub nwk_skey: NwkSKey,
+    pub app_skey: AppSKey,
+}
+
+impl AbpCredentials {
+    pub fn new(dev_addr: DevAddr, nwk_skey: NwkSKey, app_skey: AppSKey) -> Self {
+        Self {
+            dev_addr,
+            nwk_skey,
+            app_skey,
+        }
+    }
+}
+
+/// Credentials for either over-the-air activation (OTAA) or activation by personalization (ABP)
+#[derive(Debug, Clone)]
+pub enum ActivationCredentials {
+    Otaa(Credentials),
+    Abp(AbpCredentials),
+}
+
+impl From<Credentials> for ActivationCredentials {
+    fn from(credentials: Credentials) -> Self {
+        Self::Otaa(credentials)
+    }
+}
+
+impl From<AbpCredentials> for ActivationCredentials {
+    fn from(credentials: AbpCredentials) -> Self {
+        Self::Abp(credentials)
+    }
+}
+
+/// Strips the separators people paste hex with: whitespace, `:`, `-`, and a `0x` prefix on the
+/// whole string or on each separated byte, so `0x60 0x81`, `60-81` and `60:81` all give `6081`
+pub fn normalize_hex(s: &str) -> String {
+    s.split(|c: char| c.is_whitespace() || c == ':' || c == '-')
+        .map(|part| {
+            part.strip_prefix("0x")
+                .or_else(|| part.strip_prefix("0X"))
+                .unwrap_or(part)
+        })
+        .collect()
+}
+
+/// Masks the keys in `AT+KEY` commands and the modem's echoes of them, such as
+/// `AT+KEY=APPKEY, 2B7E…` or `+KEY: APPKEY 2B7E…`, so they can go into logs and errors. A key is
+/// any run of 32 or more hex digits on a line that mentions `KEY`; other text is left alone.
+pub(crate) fn redact_keys(text: &str) -> std::borrow::Cow<'_, str> {
+    if !text.to_ascii_uppercase().contains("KEY") {
+        return text.into();
+    }
+    let mut redacted = String::with_capacity(text.len());
+    for line in text.split_inclusive('\n') {
+        if !line.to_ascii_uppercase().contains("KEY") {
+            redacted.push_str(line);
+            continue;
+        }
+        let mut hex_run = String::new();
+        for c in line.chars() {
+            if c.is_ascii_hexdigit() {
+                hex_run.push(c);
+            } else {
+                push_hex_run(&mut redacted, &mut hex_run);
+                redacted.push(c);
+            }
+        }
+        push_hex_run(&mut redacted, &mut hex_run);
+    }
+    redacted.into()
+}
+
+fn push_hex_run(redacted: &mut String, hex_run: &mut String) {
+    const KEY_HEX_LEN: usize = 32;
+    if hex_run.len() >= KEY_HEX_LEN {
+        redacted.push_str("****");
+    } else {
+        redacted.push_str(hex_run);
+    }
+    hex_run.clear();
+}
+
 macro_rules! derive_from_str {
     ($name:ident, $size:expr) => {
         #[derive(Debug, Clone, PartialEq, Eq)]
         pub struct $name([u8; $size]);
 
+        derive_from_str!(@impls $name, $size);
+    };
+    // EUIs are written to the modem most significant byte first, the order they are printed on
+    // device labels and shown by most network servers. Some consoles can show them least
+    // significant byte first instead, and an EUI copied in that order must be reversed or the
+    // join will never be accepted.
+    (eui $name:ident, $size:expr) => {
+        derive_from_str!($name, $size);
+
+        impl $name {
+            /// Parses an EUI written least significant byte first
+            pub fn from_str_reversed(s: &str) -> std::result::Result<Self, ParseError> {
+                Ok(Self::from_str(s)?.reversed())
+            }
+
+            /// Formats the EUI least significant byte first. `Display` gives the most significant
+            /// byte first, which is the order the modem takes.
+            pub fn to_string_reversed(&self) -> String {
+                self.reversed().to_string()
+            }
+
+            /// The EUI with its byte order swapped
+            pub fn reversed(&self) -> Self {
+                let mut bytes = self.0;
+                bytes.reverse();
+                Self(bytes)
+            }
+        }
+    };
+    // Keys only show their last two bytes in `Debug`, so logging credentials doesn't leak them,
+    // and with the `zeroize` feature are wiped when dropped. `Display` still gives the whole key,
+    // as that is how it is sent to the modem; the commands carrying it, and the modem's echoes,
+    // go through `redact_keys` before they are logged or put in an error.
+    (secret $name:ident, $size:expr) => {
+        #[derive(Clone, PartialEq, Eq)]
+        pub struct $name([u8; $size]);
+
+        impl fmt::Debug for $name {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                let last = hex::encode_upper(&self.0[$size - 2..]);
+                write!(f, "{}(****…{last})", stringify!($name))
+            }
+        }
+
+        #[cfg(feature = "zeroize")]
+        impl Drop for $name {
+            fn drop(&mut self) {
+                zeroize::Zeroize::zeroize(&mut self.0);
+            }
+        }
+
+        #[cfg(feature = "zeroize")]
+        impl zeroize::ZeroizeOnDrop for $name {}
+
+        derive_from_str!(@impls $name, $size);
+    };
+    (@impls $name:ident, $size:expr) => {
         impl FromStr for $name {
             type Err = ParseError;
 
             fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
-                let mut s = s.to_string();
-                s.retain(|c| c != ':');
-                let byte_vec = hex::decode(&s)?;
+                let byte_vec = hex::decode(normalize_hex(s))?;
                 let len = byte_vec.len();
                 let byte_arr: [u8; $size] = byte_vec
                     .try_into()
@@ -49,71 +191,172 @@ macro_rules! derive_from_str {
                 Self(arr)
             }
         }
+
+        impl TryFrom<&[u8]> for $name {
+            type Error = ParseError;
+
+            fn try_from(bytes: &[u8]) -> std::result::Result<Self, Self::Error> {
+                let byte_arr: [u8; $size] = bytes
+                    .try_into()
+                    .map_err(|_| ParseError::VecWrongSize(bytes.len()))?;
+                Ok(Self(byte_arr))
+            }
+        }
+
+        impl $name {
+            /// The raw bytes, in the order they are written as hex
+            pub fn as_bytes(&self) -> &[u8; $size] {
+                &self.0
+            }
+        }
+
+        impl AsRef<[u8]> for $name {
+            fn as_ref(&self) -> &[u8] {
+                &self.0
+            }
+        }
+
+        #[cfg(feature = "serde")]
+        impl serde::Serialize for $name {
+            fn serialize<S: serde::Serializer>(
+                &self,
+                serializer: S,
+            ) -> std::result::Result<S::Ok, S::Error> {
+                serializer.collect_str(self)
+            }
+        }
+
+        #[cfg(feature = "serde")]
+        impl<'de> serde::Deserialize<'de> for $name {
+            fn deserialize<D: serde::Deserializer<'de>>(
+                deserializer: D,
+            ) -> std::result::Result<Self, D::Error> {
+                let s = String::deserialize(deserializer)?;
+                Self::from_str(&s).map_err(serde::de::Error::custom)
+            }
+        }
     };
 }
 
-derive_from_str!(AppEui, 8);
-derive_from_str!(DevEui, 8);
-derive_from_str!(AppKey, 16);
+derive_from_str!(eui AppEui, 8);
+derive_from_str!(eui DevEui, 8);
+derive_from_str!(secret AppKey, 16);
+derive_from_str!(DevAddr, 4);
+derive_from_str!(secret NwkSKey, 16);
+derive_from_str!(secret AppSKey, 16);
+derive_from_str!(NetId, 3);
 
 use super::*;
 
-impl<const N: usize> LoraE5<N> {
+impl<const N: usize, T: Transport> LoraE5<N, T> {
+    /// Reads the DevEui, or returns the one already read or set if the builder's `cache_ids` is on
     pub fn get_dev_eui(&mut self) -> Result<DevEui> {
         const EXPECTED_PRELUDE: &str = "+ID: DevEui, ";
-        self.write_command("AT+ID=DevEui")?;
-        let n = self.read_until_break(DEFAULT_TIMEOUT)?;
+        if let Some(dev_eui) = &self.dev_eui {
+            return Ok(dev_eui.clone());
+        }
+        let n = self.query("AT+ID=DevEui")?;
         let response = self.framed_response(n, EXPECTED_PRELUDE)?;
-        Ok(DevEui::from_str(response.trim_end())?)
+        let dev_eui = DevEui::from_str(response.trim_end())?;
+        if self.cache_ids {
+            self.dev_eui = Some(dev_eui.clone());
+        }
+        Ok(dev_eui)
     }
 
+    /// Reads the AppEui, or returns the one already read or set if the builder's `cache_ids` is on
     pub fn get_app_eui(&mut self) -> Result<AppEui> {
         const EXPECTED_PRELUDE: &str = "+ID: AppEui, ";
-        self.write_command("AT+ID=AppEui")?;
-        let n = self.read_until_break(DEFAULT_TIMEOUT)?;
+        if let Some(app_eui) = &self.app_eui {
+            return Ok(app_eui.clone());
+        }
+        let n = self.query("AT+ID=AppEui")?;
         let response = self.framed_response(n, EXPECTED_PRELUDE)?;
-        Ok(AppEui::from_str(response.trim_end())?)
+        let app_eui = AppEui::from_str(response.trim_end())?;
+        if self.cache_ids {
+            self.app_eui = Some(app_eui.clone());
+        }
+        Ok(app_eui)
+    }
+
+    /// Forgets the cached DevEui and AppEui, so the next reads query the modem
+    pub fn refresh_ids(&mut self) {
+        self.dev_eui = None;
+        self.app_eui = None;
     }
 
     pub fn set_app_eui(&mut self, app_eui: &AppEui) -> Result {
         const EXPECTED_PRELUDE: &str = "+ID: AppEui, ";
         let cmd = format!("AT+ID=AppEui, {app_eui}");
+        self.app_eui = None;
         self.write_command(&cmd)?;
-        let n = self.read_until_break(DEFAULT_TIMEOUT)?;
+        let n = self.read_until_break(self.command_timeout)?;
         let response = self.framed_response(n, EXPECTED_PRELUDE)?;
         let app_eui_response = AppEui::from_str(response.trim_end())?;
         if &app_eui_response == app_eui {
+            if self.cache_ids {
+                self.app_eui = Some(app_eui_response);
+            }
             Ok(())
         } else {
-            Err(Error::UnexpectedResponse(app_eui_response.to_string()))
+            Err(Error::unexpected_framed(
+                EXPECTED_PRELUDE,
+                app_eui,
+                response,
+            ))
         }
     }
 
     pub fn set_dev_eui(&mut self, dev_eui: &DevEui) -> Result {
         const EXPECTED_PRELUDE: &str = "+ID: DevEui, ";
         let cmd = format!("AT+ID=DevEui, {dev_eui}");
+        self.dev_eui = None;
         self.write_command(&cmd)?;
-        let n = self.read_until_break(DEFAULT_TIMEOUT)?;
+        let n = self.read_until_break(self.command_timeout)?;
         let response = self.framed_response(n, EXPECTED_PRELUDE)?;
         let dev_eui_response = DevEui::from_str(response.trim_end())?;
         if &dev_eui_response == dev_eui {
+            if self.cache_ids {
+                self.dev_eui = Some(dev_eui_response);
+            }
             Ok(())
         } else {
-            Err(Error::UnexpectedResponse(dev_eui_response.to_string()))
+            Err(Error::unexpected_framed(
+                EXPECTED_PRELUDE,
+                dev_eui,
+                response,
+            ))
+        }
+    }
+
+    /// Reads back the AppKey. Firmware that hides keys answers with asterisks, which fails with
+    /// `Error::KeyMasked`.
+    pub fn get_app_key(&mut self) -> Result<AppKey> {
+        const EXPECTED_PRELUDE: &str = "+KEY: APPKEY ";
+        let n = self.query("AT+KEY=APPKEY")?;
+        let response = self.framed_response(n, EXPECTED_PRELUDE)?.trim_end();
+        if response.contains('*') {
+            return Err(Error::KeyMasked);
         }
+        Ok(AppKey::from_str(response)?)
     }
 
     pub fn set_app_key(&mut self, app_key: &AppKey) -> Result {
         const EXPECTED_PRELUDE: &str = "+KEY: APPKEY ";
         let cmd = format!("AT+KEY=APPKEY, {app_key}");
         self.write_command(&cmd)?;
-        let n = self.read_until_break(DEFAULT_TIMEOUT)?;
+        let n = self.read_until_break(self.command_timeout)?;
         let response = self.framed_response(n, EXPECTED_PRELUDE)?;
         let app_key_response = AppKey::from_str(response.trim_end())?;
         if &app_key_response == app_key {
             Ok(())
         } else {
-            Err(Error::UnexpectedResponse(response.to_string()))
+            // only the redacted forms, so the error can be logged without leaking either key
+            Err(Error::unexpected_framed(
+                EXPECTED_PRELUDE,
+                format!("{app_key:?}"),
+                &format!("{app_key_response:?}"),
+            ))
         }
     }
 
@@ -122,6 +365,76 @@ impl<const N: usize> LoraE5<N> {
         self.set_app_eui(&credentials.app_eui)?;
         self.set_app_key(&credentials.app_key)
     }
+
+    /// Reads the DevAddr, which is assigned by the network after an OTAA join
+    pub fn get_dev_addr(&mut self) -> Result<DevAddr> {
+        const EXPECTED_PRELUDE: &str = "+ID: DevAddr, ";
+        let n = self.query("AT+ID=DevAddr")?;
+        let response = self.framed_response(n, EXPECTED_PRELUDE)?;
+        Ok(DevAddr::from_str(response.trim_end())?)
+    }
+
+    pub fn set_dev_addr(&mut self, dev_addr: &DevAddr) -> Result {
+        const EXPECTED_PRELUDE: &str = "+ID: DevAddr, ";
+        let cmd = format!("AT+ID=DevAddr, {dev_addr}");
+        self.write_command(&cmd)?;
+        let n = self.read_until_break(self.command_timeout)?;
+        let response = self.framed_response(n, EXPECTED_PRELUDE)?;
+        let dev_addr_response = DevAddr::from_str(response.trim_end())?;
+        if &dev_addr_response == dev_addr {
+            Ok(())
+        } else {
+            Err(Error::unexpected_framed(
+                EXPECTED_PRELUDE,
+                dev_addr,
+                response,
+            ))
+        }
+    }
+
+    pub fn set_nwkskey(&mut self, nwk_skey: &NwkSKey) -> Result {
+        const EXPECTED_PRELUDE: &str = "+KEY: NWKSKEY ";
+        let cmd = format!("AT+KEY=NWKSKEY, {nwk_skey}");
+        self.write_command(&cmd)?;
+        let n = self.read_until_break(self.command_timeout)?;
+        let response = self.framed_response(n, EXPECTED_PRELUDE)?;
+        let nwk_skey_response = NwkSKey::from_str(response.trim_end())?;
+        if &nwk_skey_response == nwk_skey {
+            Ok(())
+        } else {
+            // only the redacted forms, so the error can be logged without leaking either key
+            Err(Error::unexpected_framed(
+                EXPECTED_PRELUDE,
+                format!("{nwk_skey:?}"),
+                &format!("{nwk_skey_response:?}"),
+            ))
+        }
+    }
+
+    pub fn set_appskey(&mut self, app_skey: &AppSKey) -> Result {
+        const EXPECTED_PRELUDE: &str = "+KEY: APPSKEY ";
+        let cmd = format!("AT+KEY=APPSKEY, {app_skey}");
+        self.write_command(&cmd)?;
+        let n = self.read_until_break(self.command_timeout)?;
+        let response = self.framed_response(n, EXPECTED_PRELUDE)?;
+        let app_skey_response = AppSKey::from_str(response.trim_end())?;
+        if &app_skey_response == app_skey {
+            Ok(())
+        } else {
+            // only the redacted forms, so the error can be logged without leaking either key
+            Err(Error::unexpected_framed(
+                EXPECTED_PRELUDE,
+                format!("{app_skey:?}"),
+                &format!("{app_skey_response:?}"),
+            ))
+        }
+    }
+
+    pub fn set_abp_credentials(&mut self, credentials: &AbpCredentials) -> Result {
+        self.set_dev_addr(&credentials.dev_addr)?;
+        self.set_nwkskey(&credentials.nwk_skey)?;
+        self.set_appskey(&credentials.app_skey)
+    }
 }
 
 use thiserror::Error;
@@ -132,4 +445,8 @@ pub enum ParseError {
     FromHex(#[from] hex::FromHexError),
     #[error("Vec is unexpected of len {0}")]
     VecWrongSize(usize),
+    #[error("int error: {0}")]
+    Int(#[from] std::num::ParseIntError),
+    #[error("float error: {0}")]
+    Float(#[from] std::num::ParseFloatError),
 }
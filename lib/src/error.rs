@@ -1,34 +1,122 @@
-use crate::ParseError;
+use crate::{credentials::redact_keys, ParseError, Region, DR};
+use std::fmt;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
 pub enum Error {
+    #[cfg(feature = "serialport")]
     #[error("serialport error: {0}")]
     SerialPort(#[from] serialport::Error),
     #[error("utf8 error: {0}")]
     Utf8(#[from] std::str::Utf8Error),
     #[error("io error: {0}")]
     Io(#[from] std::io::Error),
-    #[error("unexpected at response: {0}")]
-    UnexpectedResponse(String),
+    #[error("unexpected at response: expected '{expected}', got '{got}'")]
+    UnexpectedResponse { expected: String, got: String },
+    #[error("modem replied ERROR({code}): {}", crate::parse::command_error_description(*.code))]
+    CommandError { code: i32 },
     #[error("partial response after timeout: \"{0}\"")]
     PartialResponse(String),
-    #[error("enabled to find port with pid = {vid} abd vid = {pid}")]
+    #[error("unable to find port with vid = {vid:#06x} and pid = {pid:#06x}")]
     PortNotFound { vid: u16, pid: u16 },
+    #[error("unable to find LoRa E5 with serial number {0}")]
+    SerialNotFound(String),
+    #[error("port was not opened by path or USB id, so it cannot be reopened")]
+    ReconnectUnsupported,
+    #[error("modem did not answer AT after {attempts} attempts")]
+    Unresponsive { attempts: u8 },
+    #[error("firmware masks the key, so it cannot be read back")]
+    KeyMasked,
     #[error("parse error: {0}")]
     Parse(#[from] ParseError),
     #[error("wrote incorrect amount of bytes: {0} instead of {1}")]
     IncorrectWrite(usize, usize),
-    #[error("ack was not received")]
-    Nack,
     #[error("failed to parse rssi/snr from: {0}")]
     FailedToParseRssiSnr(String),
+    #[error("failed to parse downlink payload from: {0}")]
+    FailedToParsePayload(String),
+    #[error("failed to parse link check answer from: {0}")]
+    FailedToParseLinkCheck(String),
+    #[error("failed to parse frame counters from: {0}")]
+    FailedToParseFrameCounters(String),
+    #[error("no link check answer was received")]
+    NoLinkCheckAnswer,
+    #[error("no downlink was received")]
+    NoDownlink,
+    #[error("failed to parse test mode packet from: {0}")]
+    FailedToParseTestPacket(String),
     #[error("failed to parse rssi from: {0}")]
     FailedToParseRssiInt(std::num::ParseIntError),
     #[error("failed to parse snr from: {0}")]
     FailedToParseSnrF32(std::num::ParseFloatError),
     #[error("invalid datarate string: {0}")]
     InvalidDatarateStr(String),
+    #[error("invalid region string: {0}")]
+    InvalidRegionStr(String),
+    #[error("modem reported a region this crate does not support: {0}")]
+    UnsupportedRegion(String),
+    #[error("invalid mode string: {0}")]
+    InvalidModeStr(String),
+    #[error("invalid class string: {0}")]
+    InvalidClassStr(String),
+    #[error("invalid version string: {0}")]
+    InvalidVersionStr(String),
+    #[error("DR{} is not supported in {}", .dr.as_str(), .region.as_str())]
+    UnsupportedDatarate { dr: DR, region: Region },
+    #[error("minimum data rate DR{} is above maximum DR{}", .min.as_str(), .max.as_str())]
+    InvalidDatarateRange { min: DR, max: DR },
+    #[error("SF{sf} at {bw_khz}kHz is not supported, expected SF7-SF12 at 125, 250 or 500kHz")]
+    InvalidRadioConfig { sf: u8, bw_khz: u16 },
+    #[error("payload of {len} bytes exceeds the {max} byte maximum for the data rate")]
+    PayloadTooLarge { len: usize, max: usize },
+    #[error("port {0} is out of range, expected 1-223")]
+    InvalidPort(u8),
+    #[error("subband {0} is out of range, expected 1-8")]
+    InvalidSubband(u8),
+    #[error("{} has no subbands", .0.as_str())]
+    SubbandUnsupported(Region),
+    #[error("provisioning failed to {step}: {source}")]
+    Provision {
+        step: &'static str,
+        source: Box<Error>,
+    },
+    #[error("script command {index} ({command}) failed: {source}")]
+    Script {
+        index: usize,
+        command: String,
+        source: Box<Error>,
+    },
+    #[error("{} has no duty cycle limit", .0.as_str())]
+    DutyCycleUnsupported(Region),
+    #[error("{0} is not supported by this firmware")]
+    Unsupported(&'static str),
     #[error("modem is busy")]
     Busy,
+    #[error("modem has not joined a network")]
+    NotJoined,
+    #[error("response filled the {0} byte buffer before completing")]
+    BufferFull(usize),
+}
+
+impl Error {
+    /// A response that doesn't match `expected`, which describes what the modem should have said
+    pub(crate) fn unexpected(expected: impl Into<String>, got: &str) -> Self {
+        Error::UnexpectedResponse {
+            expected: redact_keys(&expected.into()).into_owned(),
+            got: redact_keys(got.trim_end()).into_owned(),
+        }
+    }
+
+    /// As `unexpected`, for a response whose `prelude` has already been stripped from `got`
+    pub(crate) fn unexpected_framed(prelude: &str, expected: impl fmt::Display, got: &str) -> Self {
+        Self::unexpected(format!("{prelude}{expected}"), &format!("{prelude}{got}"))
+    }
+
+    /// Wraps the error with the provisioning step that produced it
+    pub(crate) fn context(self, step: &'static str) -> Self {
+        Error::Provision {
+            step,
+            source: Box::new(self),
+        }
+    }
 }
@@ -1,6 +1,25 @@
-use serialport::{SerialPort, SerialPortType};
+use parse::framed_response;
+
+/// Logs at debug level when the `log` feature is enabled
+macro_rules! debug {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "log")]
+        log::debug!($($arg)*);
+    };
+}
+
+/// Logs at trace level when the `log` feature is enabled
+macro_rules! trace {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "log")]
+        log::trace!($($arg)*);
+    };
+}
+
+use parse::{is_idle_read, modem_error, BUSY, NOT_JOINED, READ_IDLE_SLEEP};
 use std::{
-    io::IoSlice,
+    io,
+    str::FromStr,
     time::{self, Duration},
 };
 
@@ -15,65 +34,357 @@ pub use credentials::*;
 
 mod parse;
 
-#[cfg(test)]
+mod builder;
+pub use builder::LoraE5Builder;
+#[cfg(feature = "serialport")]
+pub use builder::PortInfo;
+
+mod transport;
+pub use transport::*;
+
+#[cfg(any(test, feature = "mock"))]
+mod mock;
+#[cfg(any(test, feature = "mock"))]
+pub use mock::MockPort;
+
+mod test_mode;
+pub use test_mode::{RadioConfig, TestPacket};
+
+mod rx_windows;
+
+mod lorawan;
+pub use lorawan::{DeviceStatus, FrameCounters};
+
+mod provision;
+pub use provision::ProvisionConfig;
+
+mod status;
+pub use status::ModemStatus;
+
+mod channel_plan;
+pub use channel_plan::Channel;
+
+mod unsolicited;
+pub use unsolicited::Unsolicited;
+
+mod transcript;
+pub use transcript::{SendEvent, SendTranscript};
+
+#[cfg(all(test, feature = "serialport"))]
 mod tests;
 
 pub const SILICON_LABS_VID: u16 = 0x10C4;
 pub const CP210X_UART_BRIDGE_PID: u16 = 0xEA60;
 
+#[cfg(any(feature = "runtime", feature = "sync"))]
+mod request;
+
 #[cfg(feature = "runtime")]
 pub mod process;
 
-pub struct LoraE5<const N: usize> {
-    port: Box<dyn SerialPort>,
+#[cfg(feature = "sync")]
+pub mod sync;
+
+#[cfg(feature = "serialport")]
+pub type DefaultTransport = Box<dyn serialport::SerialPort>;
+#[cfg(not(feature = "serialport"))]
+pub type DefaultTransport = NoTransport;
+
+pub struct LoraE5<const N: usize, T: Transport = DefaultTransport> {
+    port: T,
     buf: [u8; N],
+    region: Option<Region>,
+    command_timeout: Duration,
+    asleep: bool,
+    retries: Option<u8>,
+    joined: bool,
+    reopen: Option<Reopen<T>>,
+    /// Rate `set_uart_baud` moved the modem to, which a reopened port is switched to as well
+    uart_baud: Option<u32>,
+    last_snr: Option<f32>,
+    datarate: Option<DR>,
+    /// Port last set with `set_port`, so sends on the same port can skip `AT+PORT`
+    port_cache: Option<u8>,
+    sleep_on_close: bool,
+    closed: bool,
+    query_attempts: u8,
+    command_delay: Duration,
+    require_joined: bool,
+    line_ending: &'static str,
+    /// Whether `dev_eui`/`app_eui` are kept after the first read, as set by the builder
+    cache_ids: bool,
+    dev_eui: Option<DevEui>,
+    app_eui: Option<AppEui>,
 }
 
+impl<const N: usize, T: Transport> Drop for LoraE5<N, T> {
+    fn drop(&mut self) {
+        if !self.closed {
+            let _ = self.shut_down();
+        }
+    }
+}
+
+/// Opens a fresh transport the same way the current one was opened
+type Reopen<T> = Box<dyn FnMut() -> Result<T> + Send>;
+
 pub type Result<T = ()> = std::result::Result<T, error::Error>;
 
+/// Baud rate the modem ships with
+pub const DEFAULT_BAUD: u32 = 9600;
+
 const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+const DEFAULT_SERIAL_TIMEOUT: Duration = Duration::from_millis(10);
+const RESET_TIMEOUT: Duration = Duration::from_secs(10);
+const JOIN_TIMEOUT: Duration = Duration::from_secs(20);
+/// How long a send waits for each transmission when its airtime can't be estimated
+const SEND_TIMEOUT: Duration = Duration::from_secs(10);
+/// Time a send needs beyond its airtime: RX2 opens 2s after the uplink and may carry a downlink
+/// at the slowest data rate, plus some margin for the modem's own overhead
+const RECEIVE_WINDOWS_TIMEOUT: Duration = Duration::from_secs(6);
+/// How long a send waits for the modem to start transmitting, or to refuse to
+const SEND_START_TIMEOUT: Duration = Duration::from_secs(3);
+const ACK_RECEIVED: &str = "ACK Received";
+const FPENDING: &str = "FPENDING";
+/// How long the modem must be quiet before `is_ok` considers stale output drained
+const DRAIN_QUIET: Duration = Duration::from_millis(20);
+/// How long the modem must be quiet before a script command's response is considered complete
+const SCRIPT_QUIET: Duration = Duration::from_millis(100);
+const PROBE_ATTEMPTS: u8 = 3;
+const PROBE_DELAY: Duration = Duration::from_millis(100);
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Downlink {
     pub rssi: isize,
     pub snr: f32,
+    /// Port the downlink payload arrived on, if the downlink carried data
+    pub port: Option<u8>,
+    pub payload: Option<Vec<u8>>,
+    /// Whether the downlink acknowledged a confirmed uplink
+    pub ack: bool,
+    /// Answer to a link check requested with `request_link_check`
+    pub link_check: Option<LinkCheck>,
+    /// Whether the network has more downlinks queued (FPending). They can only arrive after an
+    /// uplink, so a Class A device should send one promptly, even an empty one, to receive them.
+    pub frame_pending: bool,
+}
+
+impl Downlink {
+    /// The payload as text, if there is one and it is valid UTF-8. Useful for downlinks received
+    /// through `send_ascii`.
+    pub fn payload_str(&self) -> Option<&str> {
+        std::str::from_utf8(self.payload.as_deref()?).ok()
+    }
+}
+
+/// What came of an uplink sent with `send` or `send_ascii`
+#[derive(Debug, Clone)]
+pub enum SendOutcome {
+    /// An unconfirmed uplink went out and nothing came back
+    Sent,
+    /// A confirmed uplink was acknowledged, with the downlink that carried the ACK if the modem
+    /// reported one
+    Acked(Option<Downlink>),
+    /// A downlink arrived that didn't acknowledge the uplink
+    Downlink(Downlink),
+    /// A confirmed uplink was not acknowledged
+    NoAck,
+}
+
+impl SendOutcome {
+    fn new(confirmed: bool, acked: bool, downlink: Option<Downlink>) -> Self {
+        match (downlink, confirmed) {
+            (downlink, true) if acked => SendOutcome::Acked(downlink),
+            (Some(downlink), _) => SendOutcome::Downlink(downlink),
+            (None, true) => SendOutcome::NoAck,
+            (None, false) => SendOutcome::Sent,
+        }
+    }
+
+    /// The downlink that came back, if any
+    pub fn downlink(&self) -> Option<&Downlink> {
+        match self {
+            SendOutcome::Acked(downlink) => downlink.as_ref(),
+            SendOutcome::Downlink(downlink) => Some(downlink),
+            SendOutcome::Sent | SendOutcome::NoAck => None,
+        }
+    }
+
+    pub fn into_downlink(self) -> Option<Downlink> {
+        match self {
+            SendOutcome::Acked(downlink) => downlink,
+            SendOutcome::Downlink(downlink) => Some(downlink),
+            SendOutcome::Sent | SendOutcome::NoAck => None,
+        }
+    }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+/// The result of `send` or `send_ascii`, with timing for airtime accounting
+#[derive(Debug, Clone)]
+pub struct SendReport {
+    pub outcome: SendOutcome,
+    /// Wall-clock time of the whole call, including the receive windows and any confirmed
+    /// retransmissions
+    pub elapsed: Duration,
+    /// Estimated time on air of a single transmission of the uplink, if the region and data rate
+    /// were set through this handle. Confirmed retransmissions add the same again each.
+    pub airtime: Option<Duration>,
+    /// Every line the modem printed for the uplink, for inspecting what happened beyond the
+    /// outcome, such as whether the network has more downlinks pending
+    pub transcript: SendTranscript,
+}
+
+/// The network's answer to a link check request (LinkCheckAns)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LinkCheck {
+    /// Demodulation margin of the last uplink, in dB above the demodulation floor
+    pub demod_margin: u8,
+    /// Number of gateways that received the last uplink
+    pub gateway_count: u8,
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum JoinResponse {
-    JoinComplete,
-    JoinFailed,
+    JoinComplete(JoinAccept),
+    JoinFailed(JoinFailure),
     AlreadyJoined,
 }
 
+/// Why a join did not complete
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JoinFailure {
+    /// The modem sent join-requests but received no join-accept, either because no gateway heard
+    /// them or because the network server rejected the device. Retrying right away rarely helps.
+    NoAccept,
+    /// The modem was busy with another operation and didn't start the join; it can be retried as
+    /// soon as that operation finishes
+    Busy,
+    /// The modem didn't finish the join before the deadline
+    Timeout,
+}
+
+/// Details of a join-accept, as far as the modem reports them
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct JoinAccept {
+    pub rssi: Option<isize>,
+    pub snr: Option<f32>,
+    pub net_id: Option<NetId>,
+    /// Address the network assigned for the session
+    pub dev_addr: Option<DevAddr>,
+}
+
+#[cfg(feature = "serialport")]
 impl<const N: usize> LoraE5<N> {
     pub fn open_usb(vid: u16, pid: u16) -> Result<Self> {
-        let available_ports = serialport::available_ports()?;
-        for port in available_ports {
-            if let SerialPortType::UsbPort(usb_port) = port.port_type {
-                if usb_port.vid == vid && usb_port.pid == pid {
-                    let port = serialport::new(&port.port_name, 9600)
-                        .timeout(Duration::from_millis(10))
-                        .open()?;
-                    return Ok(Self { port, buf: [0; N] });
-                }
-            }
-        }
-        Err(Error::PortNotFound { vid, pid })
+        LoraE5Builder::new().open_usb(vid, pid)
+    }
+
+    pub fn open_usb_with_baud(vid: u16, pid: u16, baud: u32) -> Result<Self> {
+        LoraE5Builder::new().baud(baud).open_usb(vid, pid)
+    }
+
+    /// Lists the CP210x USB serial ports, which is what the LoRa E5 dev board presents as
+    pub fn available_ports() -> Result<Vec<PortInfo>> {
+        builder::usb_ports(SILICON_LABS_VID, CP210X_UART_BRIDGE_PID)
+    }
+
+    /// Opens the LoRa E5 with the given USB serial number, as listed by `available_ports`
+    pub fn open_by_serial(serial: &str) -> Result<Self> {
+        LoraE5Builder::new().open_by_serial(serial)
     }
 
     pub fn open_path<'a>(path: impl Into<std::borrow::Cow<'a, str>>) -> Result<Self> {
-        let port = serialport::new(path, 9600)
-            .timeout(Duration::from_millis(10))
-            .open()?;
-        Ok(Self { port, buf: [0; N] })
+        LoraE5Builder::new().open_path(path)
+    }
+
+    pub fn open_path_with_baud<'a>(
+        path: impl Into<std::borrow::Cow<'a, str>>,
+        baud: u32,
+    ) -> Result<Self> {
+        LoraE5Builder::new().baud(baud).open_path(path)
+    }
+
+    pub fn builder() -> LoraE5Builder<N> {
+        LoraE5Builder::new()
+    }
+}
+
+impl<const N: usize, T: Transport> LoraE5<N, T> {
+    /// Wraps an already open transport, such as an embedded UART, with default settings
+    pub fn with_transport(transport: T) -> Self {
+        LoraE5Builder::new().build(transport)
+    }
+
+    fn new(port: T, command_timeout: Duration) -> Self {
+        Self {
+            port,
+            buf: [0; N],
+            region: None,
+            command_timeout,
+            asleep: false,
+            joined: false,
+            retries: None,
+            reopen: None,
+            uart_baud: None,
+            last_snr: None,
+            datarate: None,
+            port_cache: None,
+            sleep_on_close: false,
+            closed: false,
+            query_attempts: 1,
+            command_delay: Duration::ZERO,
+            require_joined: false,
+            line_ending: "\n",
+            cache_ids: false,
+            dev_eui: None,
+            app_eui: None,
+        }
+    }
+
+    /// Opens the port again the way it was first opened, such as after the USB serial adapter
+    /// re-enumerates and the old handle stops working. A port opened by VID/PID or serial number
+    /// is looked up again, so it may come back under a different path. Only handles opened with
+    /// `open_usb`, `open_by_serial` or `open_path` can reconnect. The port is reopened at the baud
+    /// rate it was first opened with, or at the one last set with `set_uart_baud`.
+    pub fn reconnect(&mut self) -> Result {
+        let reopen = self.reopen.as_mut().ok_or(Error::ReconnectUnsupported)?;
+        self.port = reopen()?;
+        if let Some(baud) = self.uart_baud {
+            self.port.set_baud_rate(baud)?;
+        }
+        self.clear_buffers()?;
+        self.asleep = false;
+        self.port_cache = None;
+        self.refresh_ids();
+        Ok(())
+    }
+
+    /// Closes the port cleanly, flushing pending writes and first putting the modem to sleep if
+    /// the builder's `sleep_on_close` was set. Dropping the `LoraE5` does the same but has to
+    /// ignore any error.
+    pub fn close(mut self) -> Result {
+        self.closed = true;
+        self.shut_down()
+    }
+
+    fn shut_down(&mut self) -> Result {
+        if self.sleep_on_close && !self.asleep {
+            self.sleep()?;
+        }
+        self.port.flush()?;
+        Ok(())
     }
 
     fn write_command(&mut self, cmd: &str) -> Result {
-        let n = self
-            .port
-            .write_vectored(&[IoSlice::new(cmd.as_bytes()), IoSlice::new(b"\n")])?;
-        let expected_n = cmd.len() + 1;
+        // serial ports generally don't implement vectored writes, in which case only the first
+        // slice would be written, so the line is assembled up front
+        let line = format!("{cmd}{}", self.line_ending);
+        if !self.command_delay.is_zero() {
+            std::thread::sleep(self.command_delay);
+        }
+        debug!("-> {:?}", redact_keys(&line));
+        let n = self.port.write(line.as_bytes())?;
+        let expected_n = line.len();
         if n != expected_n {
             Err(Error::IncorrectWrite(n, expected_n))
         } else {
@@ -81,25 +392,267 @@ impl<const N: usize> LoraE5<N> {
         }
     }
 
+    /// Discards the bytes the transport has buffered but not yet handed over, such as the OS
+    /// serial driver's input buffer. Unlike `drain`, which reads until the modem goes quiet, this
+    /// only throws away what is already stuck below the `LoraE5` and doesn't wait for more.
+    pub fn clear_buffers(&mut self) -> Result {
+        self.port.clear()?;
+        Ok(())
+    }
+
+    /// Reads and discards anything the modem sends until it has been quiet for `quiet_for`,
+    /// returning the number of bytes discarded. Clears out leftovers from a timed out command or
+    /// unsolicited lines so they aren't mistaken for the next response.
+    pub fn drain(&mut self, quiet_for: Duration) -> Result<usize> {
+        let mut discarded = 0;
+        let mut time = time::Instant::now();
+        while time.elapsed() < quiet_for {
+            match self.port.read(&mut self.buf) {
+                Ok(0) => std::thread::sleep(READ_IDLE_SLEEP),
+                Ok(n) => {
+                    trace!(
+                        "drained {:?}",
+                        redact_keys(&String::from_utf8_lossy(&self.buf[..n]))
+                    );
+                    discarded += n;
+                    time = time::Instant::now();
+                }
+                Err(e) if is_idle_read(&e) => (),
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(discarded)
+    }
+
+    /// Sends any AT command and returns its one-line response with `expected_prelude` stripped,
+    /// eg: `command("AT+LW=VER", "+LW: VER, ", timeout)` gives `"V104"`. Error replies are
+    /// reported like they are for built-in commands, and a response without the prelude fails
+    /// with `Error::UnexpectedResponse`.
+    pub fn command(
+        &mut self,
+        cmd: &str,
+        expected_prelude: &str,
+        timeout: Duration,
+    ) -> Result<String> {
+        self.write_raw_command(cmd)?;
+        let n = self.read_until_break(timeout)?;
+        let response = self.framed_response(n, expected_prelude)?;
+        Ok(response.trim_end().to_string())
+    }
+
+    /// Writes a command the caller composed, first forgetting whatever it may change behind the
+    /// handle's back: the port `send` would otherwise skip setting, cached IDs, and the region and
+    /// data rate that limit payload sizes. Every path that takes arbitrary commands goes through
+    /// here.
+    pub(crate) fn write_raw_command(&mut self, cmd: &str) -> Result {
+        self.invalidate_caches_for(cmd);
+        self.write_command(cmd)
+    }
+
+    fn invalidate_caches_for(&mut self, cmd: &str) {
+        let cmd = cmd.trim().to_ascii_uppercase();
+        let restarts = ["ATZ", "AT+RESET", "AT+FDEFAULT"]
+            .iter()
+            .any(|restart| cmd.starts_with(restart));
+        if restarts || cmd.starts_with("AT+PORT") {
+            self.port_cache = None;
+        }
+        if restarts || cmd.starts_with("AT+ID") {
+            self.refresh_ids();
+        }
+        // the region and data rate bound the payload `check_can_send` allows
+        let dr_arg = cmd.strip_prefix("AT+DR=").map(str::trim_start);
+        if restarts || dr_arg.is_some() || cmd.starts_with("AT+ADR=") {
+            self.datarate = None;
+        }
+        // `AT+DR=<n>` and `AT+DR=DR<n>` only set the data rate; any other argument is a region
+        let sets_region = dr_arg.is_some_and(|arg| {
+            !arg.starts_with("DR") && !arg.starts_with(|c: char| c.is_ascii_digit())
+        });
+        if restarts || sets_region {
+            self.region = None;
+        }
+    }
+
+    /// Runs each `(command, expected_prelude)` in turn, returning their responses. Unlike
+    /// `command`, every line of a response is read, with `expected_prelude` stripped from each
+    /// line that has it, and the lines are joined with `\n`: `AT+DR=DR3` prints two `+DR:` lines,
+    /// and a command whose first line is `Start`, such as `AT+JOIN` or `AT+MSG`, is read up to
+    /// its `Done` line. Stops at the first command that fails, with `Error::Script` giving its
+    /// index (from 0) and text.
+    pub fn run_script(&mut self, commands: &[(&str, &str)]) -> Result<Vec<String>> {
+        commands
+            .iter()
+            .enumerate()
+            .map(|(index, (cmd, expected_prelude))| {
+                self.script_command(cmd, expected_prelude)
+                    .map_err(|e| Error::Script {
+                        index,
+                        command: redact_keys(cmd).into_owned(),
+                        source: Box::new(e),
+                    })
+            })
+            .collect()
+    }
+
+    fn script_command(&mut self, cmd: &str, expected_prelude: &str) -> Result<String> {
+        self.write_raw_command(cmd)?;
+        let mut lines: Vec<String> = Vec::new();
+        let mut transcript = false;
+        let mut timeout = self.command_timeout;
+        loop {
+            let n = match self.read_until_break(timeout) {
+                Ok(n) => n,
+                Err(Error::PartialResponse(partial))
+                    if partial.is_empty() && !lines.is_empty() && !transcript =>
+                {
+                    break;
+                }
+                Err(e) => return Err(e),
+            };
+            for line in std::str::from_utf8(&self.buf[..n])?.lines() {
+                let line = if lines.is_empty() {
+                    framed_response(line, expected_prelude)?
+                } else if let Some(error) = modem_error(line) {
+                    return Err(error);
+                } else {
+                    line.strip_prefix(expected_prelude).unwrap_or(line)
+                };
+                lines.push(line.trim_end().to_string());
+            }
+            transcript = lines[0] == "Start";
+            if transcript && lines.last().is_some_and(|line| line == "Done") {
+                break;
+            }
+            // a transcript can go quiet for seconds while the modem waits on the network
+            timeout = if transcript {
+                JOIN_TIMEOUT
+            } else {
+                SCRIPT_QUIET
+            };
+        }
+        Ok(lines.join("\n"))
+    }
+
+    /// Checks that the modem answers `AT`, first draining any stale output
     pub fn is_ok(&mut self) -> Result<bool> {
+        self.drain(DRAIN_QUIET)?;
         self.write_command("AT")?;
         let n = self.read_until_break(Duration::from_millis(50))?;
         Ok(self.check_framed_response(n, "+AT: ", "OK").is_ok())
     }
 
+    /// Checks with `is_ok` that the modem answers, trying up to 3 times with a short pause in
+    /// between, and fails with `Error::Unresponsive` if it never does. A port can open fine while
+    /// the modem behind it is stuck, such as in its bootloader, so this turns that into an error
+    /// up front instead of on the first real command.
+    pub fn probe(&mut self) -> Result {
+        for attempt in 1..=PROBE_ATTEMPTS {
+            match self.is_ok() {
+                Ok(true) => return Ok(()),
+                Ok(false) | Err(Error::PartialResponse(_)) => {
+                    debug!("no answer to AT on attempt {attempt}");
+                    if attempt < PROBE_ATTEMPTS {
+                        std::thread::sleep(PROBE_DELAY);
+                    }
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        Err(Error::Unresponsive {
+            attempts: PROBE_ATTEMPTS,
+        })
+    }
+
     pub fn get_version(&mut self) -> Result<String> {
         const EXPECTED_PRELUDE: &str = "+VER: ";
-        self.write_command("AT+VER")?;
-        let n = self.read_until_break(DEFAULT_TIMEOUT)?;
+        let n = self.query("AT+VER")?;
         let version = self.framed_response(n, EXPECTED_PRELUDE)?;
         Ok(version.trim_end().to_string())
     }
 
+    /// Reads the firmware version with `AT+VER` and parses it into a comparable `Version`
+    pub fn get_version_parsed(&mut self) -> Result<Version> {
+        self.get_version()?.parse()
+    }
+
+    /// Restarts the modem with `ATZ` and waits for it to come back up
+    pub fn reset(&mut self) -> Result {
+        self.joined = false;
+        self.port_cache = None;
+        self.write_command("ATZ")?;
+        self.wait_for_boot(RESET_TIMEOUT)
+    }
+
+    /// Restarts the modem with `AT+RESET` and waits for it to come back up
+    pub fn soft_reset(&mut self) -> Result {
+        self.joined = false;
+        self.port_cache = None;
+        self.write_command("AT+RESET")?;
+        self.wait_for_boot(RESET_TIMEOUT)
+    }
+
+    /// Restores the modem's factory configuration with `AT+FDEFAULT`. This clears stored keys,
+    /// region and channel settings, so the modem must be reconfigured before it can join again.
+    pub fn factory_reset(&mut self) -> Result {
+        const EXPECTED_PRELUDE: &str = "+FDEFAULT: ";
+        self.port_cache = None;
+        self.refresh_ids();
+        self.write_command("AT+FDEFAULT")?;
+        let n = self.read_until_break(self.command_timeout)?;
+        self.check_framed_response(n, EXPECTED_PRELUDE, "OK")?;
+        self.region = None;
+        self.datarate = None;
+        self.joined = false;
+        Ok(())
+    }
+
+    /// Changes the modem's UART baud rate with `AT+UART=BR`. The modem acknowledges at the
+    /// current rate before switching, so the port is moved to the new rate once the echo has been
+    /// validated. The setting persists, so later connections must be opened with
+    /// `open_usb_with_baud`/`open_path_with_baud`; `reconnect` reopens at the new rate itself.
+    pub fn set_uart_baud(&mut self, baud: u32) -> Result {
+        const EXPECTED_PRELUDE: &str = "+UART: BR, ";
+        let cmd = format!("AT+UART=BR, {baud}");
+        self.write_command(&cmd)?;
+        let n = self.read_until_break(self.command_timeout)?;
+        self.check_framed_response(n, EXPECTED_PRELUDE, &baud.to_string())?;
+        self.port.flush()?;
+        self.port.set_baud_rate(baud)?;
+        self.uart_baud = Some(baud);
+        Ok(())
+    }
+
+    /// Puts the modem into low power mode with `AT+LOWPOWER`. While asleep the modem ignores
+    /// commands, so `wake` must be called before issuing any others.
+    pub fn sleep(&mut self) -> Result {
+        const EXPECTED_PRELUDE: &str = "+LOWPOWER: ";
+        self.write_command("AT+LOWPOWER")?;
+        let n = self.read_until_break(self.command_timeout)?;
+        self.check_framed_response(n, EXPECTED_PRELUDE, "SLEEP")?;
+        self.asleep = true;
+        Ok(())
+    }
+
+    /// Wakes the modem from low power mode. Does nothing if the modem was not put to sleep
+    /// through this handle.
+    pub fn wake(&mut self) -> Result {
+        const WAKEUP: &str = "+LOWPOWER: WAKEUP\r\n";
+        if !self.asleep {
+            return Ok(());
+        }
+        // any byte wakes the modem; it is otherwise ignored
+        self.port.write(&[0xFF])?;
+        self.read_until_pattern(&[WAKEUP], self.command_timeout)?;
+        self.asleep = false;
+        Ok(())
+    }
+
     pub fn set_channel(&mut self, ch: u8, enable: bool) -> Result {
         let cmd = format!("AT+CH={ch},{}", if enable { "on" } else { "off" });
         self.write_command(&cmd)?;
-        let n = self.read_until_break(DEFAULT_TIMEOUT)?;
-        self.check_framed_response(n, "+CH: CH", &format!("{ch} off"))
+        let n = self.read_until_break(self.command_timeout)?;
+        self.check_framed_response(n, "+CH: CH", &channel_response(ch, enable))
     }
 
     pub fn subband2_only(&mut self) -> Result {
@@ -109,101 +662,475 @@ impl<const N: usize> LoraE5<N> {
         Ok(())
     }
 
+    /// Leaves only EU868's three default channels (868.1, 868.3 and 868.5 MHz) enabled, which
+    /// every network listens on. Further channels are handed out by the network when joining.
+    pub fn eu868_default_channels(&mut self) -> Result {
+        for n in 0..16 {
+            self.set_channel(n, n < 3)?;
+        }
+        Ok(())
+    }
+
+    /// Sets the region and its canonical channel plan: the default channels for EU868, and
+    /// subband 2 (the one most public networks use) for US915 and AU915. Other regions use the
+    /// plan the modem loads with the region.
+    pub fn apply_region_defaults(&mut self, region: Region) -> Result {
+        self.set_region(region)?;
+        match region {
+            Region::Eu868 => self.eu868_default_channels(),
+            Region::Us915 | Region::Au915 => self.set_subband(2),
+            _ => Ok(()),
+        }
+    }
+
+    /// Restricts a US915 or AU915 modem to one of the 8 subbands, numbered 1-8: its eight 125kHz
+    /// channels and its 500kHz channel are enabled, and every other channel is disabled.
+    pub fn set_subband(&mut self, subband: u8) -> Result {
+        if !(1..=8).contains(&subband) {
+            return Err(Error::InvalidSubband(subband));
+        }
+        let first = (subband - 1) * 8;
+        let wide = 64 + subband - 1;
+        for n in 0..72 {
+            self.set_channel(n, (first..first + 8).contains(&n) || n == wide)?;
+        }
+        Ok(())
+    }
+
+    /// Reads which of the 72 US915/AU915 channels are enabled from the `AT+CH` dump, which lists
+    /// only the enabled channels. The dump is a single long line, so `N` must be large enough to
+    /// hold it.
+    pub fn get_channels(&mut self) -> Result<[bool; 72]> {
+        const EXPECTED_PRELUDE: &str = "+CH: ";
+        let n = self.query("AT+CH")?;
+        let response = self.framed_response(n, EXPECTED_PRELUDE)?;
+        parse_channels(response)
+    }
+
     pub fn set_region(&mut self, region: Region) -> Result {
         const EXPECTED_PRELUDE: &str = "+DR: ";
         let cmd = format!("AT+DR={}", region.as_str());
         self.write_command(&cmd)?;
-        let n = self.read_until_break(DEFAULT_TIMEOUT)?;
-        self.check_framed_response(n, EXPECTED_PRELUDE, region.as_str())
+        let n = self.read_until_break(self.command_timeout)?;
+        self.check_framed_response(n, EXPECTED_PRELUDE, region.as_str())?;
+        self.region = Some(region);
+        self.datarate = None;
+        Ok(())
+    }
+
+    /// Reads the region (channel plan) with `AT+DR=SCHEME`
+    pub fn get_region(&mut self) -> Result<Region> {
+        const EXPECTED_PRELUDE: &str = "+DR: ";
+        let n = self.query("AT+DR=SCHEME")?;
+        let response = self.framed_response(n, EXPECTED_PRELUDE)?.trim_end();
+        let region = Region::from_str(response)
+            .map_err(|_| Error::UnsupportedRegion(response.to_string()))?;
+        self.region = Some(region);
+        Ok(region)
+    }
+
+    pub fn get_mode(&mut self) -> Result<Mode> {
+        const EXPECTED_PRELUDE: &str = "+MODE: ";
+        let n = self.query("AT+MODE")?;
+        let response = self.framed_response(n, EXPECTED_PRELUDE)?;
+        Mode::from_str(response.trim_end())
     }
 
     pub fn set_mode(&mut self, mode: Mode) -> Result {
         const EXPECTED_PRELUDE: &str = "+MODE: ";
         let cmd = format!("AT+MODE={}", mode.as_str());
         self.write_command(&cmd)?;
-        let n = self.read_until_break(DEFAULT_TIMEOUT)?;
-        self.check_framed_response(n, EXPECTED_PRELUDE, mode.as_str())
+        let n = self.read_until_break(self.command_timeout)?;
+        self.check_framed_response(n, EXPECTED_PRELUDE, mode.as_str())?;
+        self.joined = false;
+        Ok(())
+    }
+
+    pub fn get_class(&mut self) -> Result<Class> {
+        const EXPECTED_PRELUDE: &str = "+CLASS: ";
+        let n = self.query("AT+CLASS")?;
+        let response = self.framed_response(n, EXPECTED_PRELUDE)?;
+        Class::from_str(response.trim_end())
+    }
+
+    /// Sets the device class. Class C keeps the receiver open between uplinks, so downlinks can
+    /// arrive at any time; see `poll_downlink`.
+    pub fn set_class(&mut self, class: Class) -> Result {
+        const EXPECTED_PRELUDE: &str = "+CLASS: ";
+        let cmd = format!("AT+CLASS={}", class.as_str());
+        self.write_command(&cmd)?;
+        let n = self.read_until_break(self.command_timeout)?;
+        self.check_framed_response(n, EXPECTED_PRELUDE, class.as_str())
+    }
+
+    /// Reads the current data rate, which drifts from what `set_datarate` chose when ADR is on
+    pub fn get_datarate(&mut self) -> Result<DR> {
+        const EXPECTED_PRELUDE: &str = "+DR: ";
+        let n = self.query("AT+DR")?;
+        let dr = self
+            .framed_response(n, EXPECTED_PRELUDE)?
+            .trim_end()
+            .parse()?;
+        // the modem follows up with the region's description of the data rate
+        self.read_until_break(self.command_timeout)?;
+        Ok(dr)
     }
 
+    /// Sets the data rate. The modem confirms it and then describes it for the active region, eg:
+    ///   +DR: DR5
+    ///   +DR: EU868 DR5  SF7  BW125K
+    /// Only the region and `DRx` tokens of the description are checked, as the SF/BW layout
+    /// differs between regions. If no region has been set through this handle, it is taken from
+    /// the description.
     pub fn set_datarate(&mut self, dr: DR) -> Result {
+        const EXPECTED_PRELUDE: &str = "+DR: ";
+        if let Some(region) = self.region {
+            if dr.sf_bw(region).is_none() {
+                return Err(Error::UnsupportedDatarate { dr, region });
+            }
+        }
+        let token = format!("DR{}", dr.as_str());
         let cmd = format!("AT+DR={}", dr.as_str());
         self.write_command(&cmd)?;
-        let n = self.read_until_pattern(&DR::all_patterns(), DEFAULT_TIMEOUT)?;
-        let response = std::str::from_utf8(&self.buf[..n])?;
-        if response.contains(dr.termination_pattern()) {
-            Ok(())
-        } else {
-            Err(Error::UnexpectedResponse(response.to_string()))
+        let n = self.read_until_break(self.command_timeout)?;
+        self.check_framed_response(n, EXPECTED_PRELUDE, &token)?;
+        let n = self.read_until_break(self.command_timeout)?;
+        let current = self.region;
+        let response = self.framed_response(n, EXPECTED_PRELUDE)?;
+        let mut description = response.split_whitespace();
+        let region = description.next().and_then(|region| region.parse().ok());
+        match (region, description.next()) {
+            (Some(region), Some(echoed))
+                if echoed == token && current.is_none_or(|current| current == region) =>
+            {
+                self.region = Some(region);
+                self.datarate = Some(dr);
+                Ok(())
+            }
+            _ => {
+                let region = current.as_ref().map_or("<region>", Region::as_str);
+                Err(Error::unexpected_framed(
+                    EXPECTED_PRELUDE,
+                    format!("{region} {token}"),
+                    response,
+                ))
+            }
         }
     }
 
     pub fn join(&mut self) -> Result<JoinResponse> {
+        self.join_with_timeout(false, JOIN_TIMEOUT)
+    }
+
+    pub fn force_join(&mut self) -> Result<JoinResponse> {
+        self.join_with_timeout(true, JOIN_TIMEOUT)
+    }
+
+    /// Joins like `join`, or `force_join` if `force` is set, waiting up to `timeout` rather than
+    /// the default 20 seconds. In poor coverage the modem may need several join attempts, which
+    /// can take longer.
+    pub fn join_with_timeout(&mut self, force: bool, timeout: Duration) -> Result<JoinResponse> {
         const JOIN_DONE: &str = "+JOIN: Done\r\n";
         const ALREADY_JOINED: &str = "+JOIN: Joined already\r\n";
+        const JOIN_BUSY: &str = "+JOIN: LoRaWAN modem is busy\r\n";
 
-        self.write_command("AT+JOIN")?;
-        let n = self.read_until_pattern(&[JOIN_DONE, ALREADY_JOINED], Duration::from_secs(20))?;
-        let response = std::str::from_utf8(&self.buf[..n])?;
-        Ok(if response.contains(ALREADY_JOINED) {
-            JoinResponse::AlreadyJoined
-        } else if response.contains("Network joined") {
-            JoinResponse::JoinComplete
-        } else {
-            JoinResponse::JoinFailed
-        })
+        self.write_command(if force { "AT+JOIN=FORCE" } else { "AT+JOIN" })?;
+        let join_response =
+            match self.read_until_pattern(&[JOIN_DONE, ALREADY_JOINED, JOIN_BUSY], timeout) {
+                Ok(n) => {
+                    let response = std::str::from_utf8(&self.buf[..n])?;
+                    if response.contains(ALREADY_JOINED) {
+                        JoinResponse::AlreadyJoined
+                    } else if response.contains(JOIN_BUSY) {
+                        JoinResponse::JoinFailed(JoinFailure::Busy)
+                    } else if response.contains("Network joined") {
+                        JoinResponse::JoinComplete(parse_join_accept(response)?)
+                    } else {
+                        JoinResponse::JoinFailed(JoinFailure::NoAccept)
+                    }
+                }
+                Err(Error::PartialResponse(_)) => JoinResponse::JoinFailed(JoinFailure::Timeout),
+                Err(e) => return Err(e),
+            };
+        self.joined = matches!(
+            join_response,
+            JoinResponse::JoinComplete(_) | JoinResponse::AlreadyJoined
+        );
+        Ok(join_response)
     }
 
-    pub fn force_join(&mut self) -> Result<JoinResponse> {
-        const JOIN_DONE: &str = "+JOIN: Done\r\n";
-        self.write_command("AT+JOIN=FORCE")?;
-        let n = self.read_until_pattern(&[JOIN_DONE], Duration::from_secs(20))?;
-        let response = std::str::from_utf8(&self.buf[..n])?;
-        Ok(if response.contains("Network joined") {
-            JoinResponse::JoinComplete
-        } else {
-            JoinResponse::JoinFailed
-        })
+    /// Joins like `join_with_timeout` with the default timeout, trying again after `backoff` when
+    /// the join fails, up to `attempts` tries in total (0 is treated as 1). Returns the first
+    /// successful response, or the last failure. Errors, unlike failed joins, aren't retried.
+    pub fn join_with_retries(
+        &mut self,
+        force: bool,
+        attempts: u8,
+        backoff: Duration,
+    ) -> Result<JoinResponse> {
+        let mut attempt = 1;
+        loop {
+            let join_response = self.join_with_timeout(force, JOIN_TIMEOUT)?;
+            match join_response {
+                JoinResponse::JoinFailed(_failure) if attempt < attempts => {
+                    debug!("join attempt {attempt} failed: {_failure:?}");
+                    attempt += 1;
+                    std::thread::sleep(backoff);
+                }
+                join_response => return Ok(join_response),
+            }
+        }
+    }
+
+    /// Whether a join has succeeded through this handle since it was opened, reset or switched
+    /// modes. The modem has no command to query its session state, so this is tracked locally
+    /// and costs no traffic; a session joined by an earlier process shows up as not joined until
+    /// `join` is called, which answers `AlreadyJoined` without sending a join request.
+    pub fn joined_this_session(&self) -> bool {
+        self.joined
+    }
+
+    /// Queues a LinkCheckReq MAC command with `AT+LW=LCR`. The request rides on the next uplink,
+    /// so it only takes effect once followed by a `send`, whose `Downlink` then carries the
+    /// answer in `link_check`.
+    pub fn request_link_check(&mut self) -> Result {
+        const EXPECTED_PRELUDE: &str = "+LW: ";
+        self.write_command("AT+LW=LCR")?;
+        let n = self.read_until_break(self.command_timeout)?;
+        self.check_framed_response(n, EXPECTED_PRELUDE, "LCR")
+    }
+
+    /// Requests a link check and sends an empty unconfirmed uplink on `port` to carry it,
+    /// returning the network's answer.
+    pub fn link_check(&mut self, port: u8) -> Result<LinkCheck> {
+        self.request_link_check()?;
+        self.send(&[], port, false)?
+            .outcome
+            .into_downlink()
+            .and_then(|downlink| downlink.link_check)
+            .ok_or(Error::NoLinkCheckAnswer)
+    }
+
+    /// Sets how many times the modem retransmits a confirmed uplink that isn't acknowledged
+    pub fn set_retries(&mut self, count: u8) -> Result {
+        const EXPECTED_PRELUDE: &str = "+RETRY: ";
+        let cmd = format!("AT+RETRY={count}");
+        self.write_command(&cmd)?;
+        let n = self.read_until_break(self.command_timeout)?;
+        self.check_framed_response(n, EXPECTED_PRELUDE, &count.to_string())?;
+        self.retries = Some(count);
+        Ok(())
+    }
+
+    pub fn get_retries(&mut self) -> Result<u8> {
+        const EXPECTED_PRELUDE: &str = "+RETRY: ";
+        let n = self.query("AT+RETRY")?;
+        let response = self.framed_response(n, EXPECTED_PRELUDE)?;
+        let count = response.trim_end().parse().map_err(ParseError::from)?;
+        self.retries = Some(count);
+        Ok(count)
+    }
+
+    /// How long `send` and `send_ascii` wait for an uplink of `len` bytes to complete. Each
+    /// transmission is given its airtime plus the receive windows when the region and data rate
+    /// are known, and 10 seconds otherwise. Confirmed uplinks may be retransmitted, so when the
+    /// retry count is known the wait is extended to cover every attempt.
+    fn send_timeout(&self, len: usize, confirmed: bool) -> Duration {
+        let per_attempt = match self.airtime(len) {
+            Some(airtime) => airtime + RECEIVE_WINDOWS_TIMEOUT,
+            None => SEND_TIMEOUT,
+        };
+        match self.retries {
+            Some(retries) if confirmed => per_attempt * (u32::from(retries) + 1),
+            _ => per_attempt,
+        }
     }
 
+    /// Sets the application port for following uplinks. LoRaWAN reserves port 0 for MAC commands
+    /// and 224 and up for testing, so only 1-223 are accepted. The port is remembered, and sends
+    /// only issue `AT+PORT` when their port differs from it or after a reset.
     pub fn set_port(&mut self, port: u8) -> Result {
         const EXPECTED_PRELUDE: &str = "+PORT: ";
+        if !(1..=223).contains(&port) {
+            return Err(Error::InvalidPort(port));
+        }
         let cmd = format!("AT+PORT={port}");
+        self.port_cache = None;
         self.write_command(&cmd)?;
-        let n = self.read_until_break(DEFAULT_TIMEOUT)?;
-        self.check_framed_response(n, EXPECTED_PRELUDE, &port.to_string())
+        let n = self.read_until_break(self.command_timeout)?;
+        self.check_framed_response(n, EXPECTED_PRELUDE, &port.to_string())?;
+        self.port_cache = Some(port);
+        Ok(())
     }
 
-    pub fn send(&mut self, data: &[u8], port: u8, confirmed: bool) -> Result<Option<Downlink>> {
-        self.set_port(port)?;
-        let start_line = if confirmed {
-            "+CMSGHEX: Start\r\n"
-        } else {
-            "+MSGHEX: Start\r\n"
+    /// Sets the port unless it is already the one last set through this handle
+    fn use_port(&mut self, port: u8) -> Result {
+        if self.port_cache == Some(port) {
+            return Ok(());
+        }
+        self.set_port(port)
+    }
+
+    /// Requests a transmit power in dBm. The modem clamps the request to what the current region
+    /// allows, so the power actually applied is returned.
+    pub fn set_tx_power(&mut self, dbm: u8) -> Result<u8> {
+        const EXPECTED_PRELUDE: &str = "+POWER: ";
+        let cmd = format!("AT+POWER={dbm}");
+        self.write_command(&cmd)?;
+        let n = self.read_until_break(self.command_timeout)?;
+        let response = self.framed_response(n, EXPECTED_PRELUDE)?;
+        Ok(response.trim_end().parse().map_err(ParseError::from)?)
+    }
+
+    pub fn get_tx_power(&mut self) -> Result<u8> {
+        const EXPECTED_PRELUDE: &str = "+POWER: ";
+        let n = self.query("AT+POWER")?;
+        let response = self.framed_response(n, EXPECTED_PRELUDE)?;
+        Ok(response.trim_end().parse().map_err(ParseError::from)?)
+    }
+
+    /// Reads the modem's supply voltage, in volts
+    pub fn get_vdd(&mut self) -> Result<f32> {
+        const EXPECTED_PRELUDE: &str = "+VDD: ";
+        let n = self.query("AT+VDD")?;
+        let response = self.framed_response(n, EXPECTED_PRELUDE)?;
+        let volts = response.trim_end().trim_end_matches('V').trim_end();
+        Ok(volts.parse().map_err(ParseError::from)?)
+    }
+
+    /// Reads the modem's internal temperature in Celsius with `AT+TEMP`. Firmware without a
+    /// temperature sensor answers with an error, which fails with `Error::Unsupported`.
+    pub fn get_temperature(&mut self) -> Result<f32> {
+        const EXPECTED_PRELUDE: &str = "+TEMP: ";
+        let n = self.query("AT+TEMP")?;
+        let response = match self.framed_response(n, EXPECTED_PRELUDE) {
+            Err(Error::CommandError { .. }) => return Err(Error::Unsupported("AT+TEMP")),
+            response => response?.trim_end(),
         };
+        if response.starts_with("ERROR") {
+            return Err(Error::Unsupported("AT+TEMP"));
+        }
+        Ok(response.parse().map_err(ParseError::from)?)
+    }
+
+    pub fn set_adr(&mut self, enable: bool) -> Result {
+        const EXPECTED_PRELUDE: &str = "+ADR: ";
+        let state = if enable { "ON" } else { "OFF" };
+        let cmd = format!("AT+ADR={state}");
+        self.write_command(&cmd)?;
+        let n = self.read_until_break(self.command_timeout)?;
+        self.check_framed_response(n, EXPECTED_PRELUDE, state)?;
+        if enable {
+            // the network picks the data rate from here on
+            self.datarate = None;
+        }
+        Ok(())
+    }
+
+    pub fn get_adr(&mut self) -> Result<bool> {
+        const EXPECTED_PRELUDE: &str = "+ADR: ";
+        let n = self.query("AT+ADR")?;
+        let response = self.framed_response(n, EXPECTED_PRELUDE)?;
+        match response.trim_end() {
+            "ON" => Ok(true),
+            "OFF" => Ok(false),
+            _ => Err(Error::unexpected_framed(
+                EXPECTED_PRELUDE,
+                "<ON|OFF>",
+                response,
+            )),
+        }
+    }
+
+    /// Sends `data` on `port`, reporting whether a confirmed uplink was acknowledged, any
+    /// downlink received in either receive window, and how long the uplink took
+    pub fn send(&mut self, data: &[u8], port: u8, confirmed: bool) -> Result<SendReport> {
+        let timeout = self.send_timeout(data.len(), confirmed);
+        self.send_with_timeout(data, port, confirmed, timeout)
+    }
+
+    /// Like `send`, but waits up to `timeout` for the modem to finish the uplink and its receive
+    /// windows. Slow data rates and confirmed retries may need longer than the default.
+    pub fn send_with_timeout(
+        &mut self,
+        data: &[u8],
+        port: u8,
+        confirmed: bool,
+        timeout: Duration,
+    ) -> Result<SendReport> {
+        self.send_hex(data, Some(port), confirmed, timeout)
+    }
+
+    /// Sends an uplink without a payload on `port`, only to open the receive windows. Use it to
+    /// fetch the downlinks a network has queued when `Downlink::frame_pending` is set, rather than
+    /// waiting for the next scheduled uplink.
+    pub fn send_empty(&mut self, port: u8, confirmed: bool) -> Result<Option<Downlink>> {
+        Ok(self.send(&[], port, confirmed)?.outcome.into_downlink())
+    }
+
+    /// Sends `data` like `send`, but on whatever port the modem is already set to, without
+    /// issuing `AT+PORT`. Set the port once with `set_port` before sending a burst this way.
+    pub fn send_raw(&mut self, data: &[u8], confirmed: bool) -> Result<SendReport> {
+        let timeout = self.send_timeout(data.len(), confirmed);
+        self.send_hex(data, None, confirmed, timeout)
+    }
+
+    fn send_hex(
+        &mut self,
+        data: &[u8],
+        port: Option<u8>,
+        confirmed: bool,
+        timeout: Duration,
+    ) -> Result<SendReport> {
+        let start = time::Instant::now();
+        let airtime = self.airtime(data.len());
+        let (outcome, transcript) = self.uplink_hex(data, port, confirmed, timeout)?;
+        Ok(SendReport {
+            outcome,
+            elapsed: start.elapsed(),
+            airtime,
+            transcript,
+        })
+    }
+
+    fn uplink_hex(
+        &mut self,
+        data: &[u8],
+        port: Option<u8>,
+        confirmed: bool,
+        timeout: Duration,
+    ) -> Result<(SendOutcome, SendTranscript)> {
+        self.check_can_send(data.len())?;
+        if let Some(port) = port {
+            self.use_port(port)?;
+        }
+        let command = if confirmed { "CMSGHEX" } else { "MSGHEX" };
+        let start_line = format!("+{command}: Start\r\n");
+        let busy_line = format!("+{command}: {BUSY}\r\n");
+        let not_joined_line = format!("+{command}: {NOT_JOINED}\r\n");
 
-        let busy_line = if confirmed {
-            "+CMSGHEX: LoRaWAN modem is busy\r\n"
+        // without a payload the modem sends an empty frame, which the `=""` form doesn't do
+        let cmd = if data.is_empty() {
+            format!("AT+{command}")
         } else {
-            "+MSGHEX: LoRaWAN modem is busy\r\n"
+            format!("AT+{command}=\"{}\"", hex::encode(data))
         };
-
-        let hex = hex::encode(data);
-        let cmd = format!(
-            "AT+{}=\"{hex}\"",
-            if confirmed { "CMSGHEX" } else { "MSGHEX" }
-        );
         self.write_command(&cmd)?;
         // wait for the Start
-        let n = self.read_until_pattern(&[start_line, busy_line], Duration::from_secs(3))?;
+        let n = self.read_until_pattern(
+            &[&start_line, &busy_line, &not_joined_line],
+            SEND_START_TIMEOUT,
+        )?;
         let response = std::str::from_utf8(&self.buf[..n])?;
-        let busy = response == busy_line;
-        let end_line = if confirmed {
-            "+CMSGHEX: Done\r\n"
-        } else {
-            "+MSGHEX: Done\r\n"
-        };
+        if response.ends_with(&not_joined_line) {
+            self.joined = false;
+            return Err(Error::NotJoined);
+        }
+        let busy = response.ends_with(&busy_line);
+        let mut transcript = response.to_string();
+        let end_line = format!("+{command}: Done\r\n");
         // wait for the Done
-        let n = self.read_until_pattern(&[end_line], Duration::from_secs(10))?;
+        let n = self.read_until_pattern(&[&end_line], timeout)?;
         let response = std::str::from_utf8(&self.buf[..n])?;
 
         if busy {
@@ -211,69 +1138,332 @@ impl<const N: usize> LoraE5<N> {
         }
 
         // if we weren't busy, we may have gotten some attributes
-        if let Some(m) = response.find("RXWIN1") {
-            let (rssi, snr) = parse_rssi_snr(response, m)?;
-            Ok(Some(Downlink { rssi, snr }))
-        } else if let Some(m) = response.find("RXWIN2") {
-            let (rssi, snr) = parse_rssi_snr(response, m)?;
-            Ok(Some(Downlink { rssi, snr }))
-        } else if confirmed {
-            // we expect a downlink when sending confirmed uplinks
-            // todo: check for ACK in response
-            Err(Error::Nack)
-        } else {
-            Ok(None)
+        let downlink = parse_downlink(response)?;
+        if let Some(downlink) = &downlink {
+            self.last_snr = Some(downlink.snr);
         }
+        let acked = response.contains(ACK_RECEIVED);
+        transcript.push_str(response);
+        Ok((
+            SendOutcome::new(confirmed, acked, downlink),
+            SendTranscript::parse(&transcript)?,
+        ))
+    }
+
+    /// Sends `data` as text on `port`. The text is hex-encoded for `AT+MSG`, which sends those hex
+    /// digits as the payload, so the uplink carries two bytes for every byte of `data` and the
+    /// payload size limit is checked against that. A downlink's payload is the text the modem
+    /// received, which `Downlink::payload_str` returns if it is valid UTF-8.
+    pub fn send_ascii(&mut self, data: &str, port: u8, confirmed: bool) -> Result<SendReport> {
+        let timeout = self.send_timeout(text_payload_len(data), confirmed);
+        self.send_ascii_with_timeout(data, port, confirmed, timeout)
     }
 
-    pub fn send_ascii(
+    pub fn send_ascii_with_timeout(
         &mut self,
         data: &str,
         port: u8,
         confirmed: bool,
-    ) -> Result<Option<Downlink>> {
-        self.set_port(port)?;
-        let end_line = if confirmed {
-            "+CMSG: Done\r\n"
-        } else {
-            "+MSG: Done\r\n"
-        };
+        timeout: Duration,
+    ) -> Result<SendReport> {
+        let start = time::Instant::now();
+        let airtime = self.airtime(text_payload_len(data));
+        let (outcome, transcript) = self.uplink_text(data, port, confirmed, timeout)?;
+        Ok(SendReport {
+            outcome,
+            elapsed: start.elapsed(),
+            airtime,
+            transcript,
+        })
+    }
+
+    fn uplink_text(
+        &mut self,
+        data: &str,
+        port: u8,
+        confirmed: bool,
+        timeout: Duration,
+    ) -> Result<(SendOutcome, SendTranscript)> {
+        self.check_can_send(text_payload_len(data))?;
+        self.use_port(port)?;
+        let command = if confirmed { "CMSG" } else { "MSG" };
+        let end_line = format!("+{command}: Done\r\n");
+        let not_joined_line = format!("+{command}: {NOT_JOINED}\r\n");
         let hex = hex::encode(data);
-        let cmd = format!("AT+{}=\"{hex}\"", if confirmed { "CMSG" } else { "MSG" });
+        let cmd = format!("AT+{command}=\"{hex}\"");
         self.write_command(&cmd)?;
-        let n = self.read_until_pattern(&[end_line], Duration::from_secs(3))?;
-        let response = std::str::from_utf8(&self.buf[..n])?;
+        let n = self.read_until_pattern(&[&end_line, &not_joined_line], timeout)?;
+        let response = &self.buf[..n];
+        if response.ends_with(not_joined_line.as_bytes()) {
+            self.joined = false;
+            return Err(Error::NotJoined);
+        }
+        let busy_line = format!("+{command}: {BUSY}\r\n");
+        if response
+            .windows(busy_line.len())
+            .any(|line| line == busy_line.as_bytes())
+        {
+            return Err(Error::Busy);
+        }
 
-        if let Some(m) = response.find("RXWIN1") {
-            let (rssi, snr) = parse_rssi_snr(response, m)?;
-            Ok(Some(Downlink { rssi, snr }))
-        } else if let Some(m) = response.find("RXWIN2") {
-            let (rssi, snr) = parse_rssi_snr(response, m)?;
-            Ok(Some(Downlink { rssi, snr }))
-        } else if confirmed {
-            // we expect a downlink when sending confirmed uplinks
-            // todo: check for ACK in response
-            Err(Error::Nack)
-        } else {
-            Ok(None)
+        let downlink = parse_ascii_downlink(response)?;
+        if let Some(downlink) = &downlink {
+            self.last_snr = Some(downlink.snr);
+        }
+        let response = String::from_utf8_lossy(response);
+        let acked = response.contains(ACK_RECEIVED);
+        Ok((
+            SendOutcome::new(confirmed, acked, downlink),
+            SendTranscript::parse(&response)?,
+        ))
+    }
+
+    /// Time on air of an uplink carrying `len` bytes, if the region and data rate are known
+    fn airtime(&self, len: usize) -> Option<Duration> {
+        let (region, dr) = self.region.zip(self.datarate)?;
+        airtime(region, dr, len)
+    }
+
+    /// Fails with `Error::NotJoined` if the builder's `require_joined` is set and no join has
+    /// succeeded through this handle, or with `Error::PayloadTooLarge` if `len` bytes exceed what
+    /// the data rate last set with `set_datarate` can carry. The payload size isn't checked when
+    /// the data rate isn't known, such as under ADR.
+    fn check_can_send(&self, len: usize) -> Result {
+        if self.require_joined && !self.joined {
+            return Err(Error::NotJoined);
+        }
+        let max = self
+            .region
+            .zip(self.datarate)
+            .and_then(|(region, dr)| max_payload_size(region, dr));
+        match max {
+            Some(max) if len > max => Err(Error::PayloadTooLarge { len, max }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Waits for an unsolicited downlink, such as those delivered to Class C devices outside of
+    /// an uplink's receive windows. Returns `None` if no downlink arrives before `timeout`; any
+    /// incomplete line read before then is discarded.
+    pub fn poll_downlink(&mut self, timeout: Duration) -> Result<Option<Downlink>> {
+        let deadline = time::Instant::now() + timeout;
+        let mut response = String::new();
+        loop {
+            let remaining = deadline.saturating_duration_since(time::Instant::now());
+            if remaining.is_zero() {
+                return Ok(None);
+            }
+            match self.read_until_break(remaining) {
+                Ok(n) => {
+                    let line = std::str::from_utf8(&self.buf[..n])?;
+                    response.push_str(line);
+                    if line.contains("RSSI") {
+                        let downlink = parse_downlink(&response)?;
+                        if let Some(downlink) = &downlink {
+                            self.last_snr = Some(downlink.snr);
+                        }
+                        return Ok(downlink);
+                    }
+                }
+                Err(Error::PartialResponse(_)) => return Ok(None),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Sends `data` unconfirmed on `port` and waits for a reply on `reply_port`, or for any
+    /// downlink carrying a payload if `reply_port` is `None`. The reply may arrive in the
+    /// uplink's receive windows or, for Class C devices, later through `poll_downlink`; other
+    /// downlinks received in the meantime are discarded. Fails with `Error::NoDownlink` if no
+    /// reply arrives within `timeout` of the call.
+    pub fn send_await_downlink(
+        &mut self,
+        data: &[u8],
+        port: u8,
+        reply_port: Option<u8>,
+        timeout: Duration,
+    ) -> Result<Downlink> {
+        let deadline = time::Instant::now() + timeout;
+        let is_reply = |downlink: &Downlink| match reply_port {
+            Some(reply_port) => downlink.port == Some(reply_port),
+            None => downlink.payload.is_some(),
+        };
+        let mut downlink = self.send(data, port, false)?.outcome.into_downlink();
+        loop {
+            if let Some(downlink) = downlink.filter(is_reply) {
+                return Ok(downlink);
+            }
+            let remaining = deadline.saturating_duration_since(time::Instant::now());
+            if remaining.is_zero() {
+                return Err(Error::NoDownlink);
+            }
+            downlink = self.poll_downlink(remaining)?;
         }
     }
 }
 
-pub(crate) fn parse_rssi_snr(response: &str, m: usize) -> Result<(isize, f32)> {
-    let (_, remaining_str) = response.split_at(m);
-    if let Some(n) = remaining_str.find("\r\n") {
-        let (line, _) = remaining_str.split_at(n);
-        let (_, signal) = line.split_at(", RSSI ".len());
-        if let Some(n) = signal.find(", ") {
-            let (rssi_remainder, snr_remainder) = signal.split_at(n);
-            let (_, rssi) = rssi_remainder.split_at(" RSSI ".len());
-            let (_, snr) = snr_remainder.split_at(", SNR ".len());
-            return Ok((
-                rssi.parse().map_err(Error::FailedToParseRssiInt)?,
-                snr.parse().map_err(Error::FailedToParseSnrF32)?,
-            ));
+/// Collects what the modem printed about a join-accept, eg:
+///   +JOIN: NetID 000024 DevAddr 48:00:00:01
+///   +JOIN: RXWIN1, RSSI -45, SNR 9.0
+pub(crate) fn parse_join_accept(response: &str) -> Result<JoinAccept> {
+    let mut join_accept = JoinAccept::default();
+    for line in response.lines() {
+        let line = line.trim_end();
+        if let Some(ids) = line.strip_prefix("+JOIN: NetID ") {
+            let mut ids = ids.split_whitespace();
+            join_accept.net_id = ids.next().and_then(|net_id| net_id.parse().ok());
+            if ids.next() == Some("DevAddr") {
+                // printed as 48:00:00:01 by current firmware, but plain hex is accepted too
+                join_accept.dev_addr = ids.next().and_then(|dev_addr| dev_addr.parse().ok());
+            }
+        } else if let Some(m) = line.find("RXWIN") {
+            let (rssi, snr) = parse_rssi_snr(line, m)?;
+            join_accept.rssi = Some(rssi);
+            join_accept.snr = Some(snr);
+        }
+    }
+    Ok(join_accept)
+}
+
+/// Formats a frequency in Hz as the MHz value AT commands expect, eg: 868100000 => "868.1"
+pub(crate) fn format_mhz(freq_hz: u32) -> String {
+    let mhz = freq_hz / 1_000_000;
+    let fraction = freq_hz % 1_000_000;
+    if fraction == 0 {
+        mhz.to_string()
+    } else {
+        let fraction = format!("{fraction:06}");
+        format!("{mhz}.{}", fraction.trim_end_matches('0'))
+    }
+}
+
+pub(crate) fn channel_response(ch: u8, enable: bool) -> String {
+    format!("{ch} {}", if enable { "on" } else { "off" })
+}
+
+/// Parses an `AT+CH` dump such as `3; 0,868100000,DR0,DR5; 1,868300000,DR0,DR5; ...`, where
+/// each entry after the count starts with an enabled channel's index
+pub(crate) fn parse_channels(response: &str) -> Result<[bool; 72]> {
+    let mut channels = [false; 72];
+    for entry in response.trim_end().split(';').skip(1) {
+        let index = entry.split(',').next().unwrap_or_default().trim();
+        let index: usize = index.parse().map_err(ParseError::from)?;
+        match channels.get_mut(index) {
+            Some(channel) => *channel = true,
+            None => return Err(Error::unexpected("channel indexes 0-71", response)),
         }
     }
+    Ok(channels)
+}
+
+/// Size on air of the payload `send_ascii` sends for `data`, which goes out as its hex encoding
+fn text_payload_len(data: &str) -> usize {
+    data.len() * 2
+}
+
+pub(crate) fn parse_downlink(response: &str) -> Result<Option<Downlink>> {
+    parse_downlink_with(response.as_bytes(), |hex| {
+        Ok(hex::decode(hex).map_err(ParseError::from)?)
+    })
+}
+
+/// Parses the downlink from an `AT+MSG`/`AT+CMSG` response, whose payload is the received text
+/// rather than hex. The payload is kept as raw bytes, so it survives even if it isn't UTF-8.
+pub(crate) fn parse_ascii_downlink(response: &[u8]) -> Result<Option<Downlink>> {
+    parse_downlink_with(response, |text| Ok(text.to_vec()))
+}
+
+fn parse_downlink_with(
+    raw: &[u8],
+    decode: impl Fn(&[u8]) -> Result<Vec<u8>>,
+) -> Result<Option<Downlink>> {
+    // everything but the payload is ASCII, so a lossy view is enough to parse it
+    let response = String::from_utf8_lossy(raw);
+    let m = match response.find("RXWIN") {
+        Some(m) => m,
+        None => return Ok(None),
+    };
+    let (rssi, snr) = parse_rssi_snr(&response, m)?;
+    let (port, payload) = match parse_rx_payload(raw)? {
+        Some((port, payload)) => (Some(port), Some(decode(payload)?)),
+        None => (None, None),
+    };
+    Ok(Some(Downlink {
+        rssi,
+        snr,
+        port,
+        payload,
+        ack: response.contains(ACK_RECEIVED),
+        link_check: parse_link_check(&response)?,
+        // matched by line, as a text payload could contain the word
+        frame_pending: response
+            .lines()
+            .any(|line| line.trim_end().ends_with(&format!(": {FPENDING}"))),
+    }))
+}
+
+/// Parses a link check answer such as `+MSGHEX: Link 20, 1`
+pub(crate) fn parse_link_check(response: &str) -> Result<Option<LinkCheck>> {
+    let m = match response.find(": Link ") {
+        Some(m) => m,
+        None => return Ok(None),
+    };
+    let (_, remaining_str) = response.split_at(m + ": Link ".len());
+    let line = remaining_str.lines().next().unwrap_or(remaining_str);
+    if let Some((margin, count)) = line.trim_end().split_once(", ") {
+        if let (Ok(demod_margin), Ok(gateway_count)) = (margin.parse(), count.parse()) {
+            return Ok(Some(LinkCheck {
+                demod_margin,
+                gateway_count,
+            }));
+        }
+    }
+    Err(Error::FailedToParseLinkCheck(response.to_string()))
+}
+
+/// Finds the port and the still encoded payload in a line such as `+MSGHEX: PORT: 1; RX: "0102"`
+/// or `+MSG: PORT: 1; RX: "text"`. Some firmware omits the colon after `RX`.
+pub(crate) fn parse_rx_payload(response: &[u8]) -> Result<Option<(u8, &[u8])>> {
+    const PORT: &[u8] = b"PORT: ";
+    let m = match response.windows(PORT.len()).position(|w| w == PORT) {
+        Some(m) => m,
+        None => return Ok(None),
+    };
+    let remaining = &response[m + PORT.len()..];
+    let line = remaining.split(|&b| b == b'\n').next().unwrap_or(remaining);
+    if let Some(semicolon) = line.iter().position(|&b| b == b';') {
+        let (port, rx) = line.split_at(semicolon);
+        let rx = rx[1..].trim_ascii_start();
+        let payload = rx.strip_prefix(b"RX").and_then(|rx| {
+            let start = rx.iter().position(|&b| b != b':' && b != b' ')?;
+            let quoted = rx[start..].strip_prefix(b"\"")?;
+            let end = quoted.iter().rposition(|&b| b == b'"')?;
+            Some(&quoted[..end])
+        });
+        if let (Some(payload), Ok(port)) = (payload, std::str::from_utf8(port)) {
+            let port = port.trim().parse().map_err(ParseError::from)?;
+            return Ok(Some((port, payload)));
+        }
+    }
+    Err(Error::FailedToParsePayload(
+        String::from_utf8_lossy(response).to_string(),
+    ))
+}
+
+/// Parses the signal quality from a line such as `+MSG: RXWIN1, RSSI -50, SNR 8.0`, where `m`
+/// is the position of `RXWIN`. Text (`+MSG`/`+CMSG`) and hex (`+MSGHEX`/`+CMSGHEX`) uplinks
+/// print the same line.
+pub(crate) fn parse_rssi_snr(response: &str, m: usize) -> Result<(isize, f32)> {
+    let line = response[m..].lines().next().unwrap_or_default();
+    let field = |name| {
+        line.split(',')
+            .find_map(|field| field.trim().strip_prefix(name))
+    };
+    if let (Some(rssi), Some(snr)) = (field("RSSI "), field("SNR ")) {
+        return Ok((
+            rssi.parse().map_err(Error::FailedToParseRssiInt)?,
+            snr.parse().map_err(Error::FailedToParseSnrF32)?,
+        ));
+    }
     Err(Error::FailedToParseRssiSnr(response.to_string()))
 }
@@ -0,0 +1,168 @@
+use super::*;
+
+/// The LoRaWAN session's frame counters
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct FrameCounters {
+    pub uplink: u32,
+    pub downlink: u32,
+}
+
+/// What the modem answers to the network's DevStatusReq MAC command
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeviceStatus {
+    /// Battery level as reported to the network: 0 for external power, 1-254 for the charge
+    /// level, and 255 when it cannot be measured
+    pub battery: u8,
+    /// SNR in dB of the last downlink, rounded and clamped to -32..=31 as in DevStatusAns, or
+    /// `None` if no downlink has been received through this handle
+    pub margin: Option<i8>,
+}
+
+impl<const N: usize, T: Transport> LoraE5<N, T> {
+    /// Sets whether the 400ms dwell time limit applies to uplinks and downlinks, with
+    /// `AT+LW=DWELL`. Only AS923 and AU915 define dwell time; with it on, the modem rejects the
+    /// data rates whose airtime would exceed the limit.
+    pub fn set_dwell_time(&mut self, uplink: bool, downlink: bool) -> Result {
+        const EXPECTED_PRELUDE: &str = "+LW: DWELL, ";
+        let state = format!("{}, {}", on_off(uplink), on_off(downlink));
+        let cmd = format!("AT+LW=DWELL, {state}");
+        self.write_command(&cmd)?;
+        let n = self.read_until_break(self.command_timeout)?;
+        self.check_framed_response(n, EXPECTED_PRELUDE, &state)
+    }
+
+    /// Reads whether the dwell time limit applies to uplinks and downlinks, in that order
+    pub fn get_dwell_time(&mut self) -> Result<(bool, bool)> {
+        const EXPECTED_PRELUDE: &str = "+LW: DWELL, ";
+        let n = self.query("AT+LW=DWELL")?;
+        let response = self.framed_response(n, EXPECTED_PRELUDE)?;
+        let unexpected =
+            || Error::unexpected_framed(EXPECTED_PRELUDE, "<ON|OFF>, <ON|OFF>", response);
+        let (uplink, downlink) = response
+            .trim_end()
+            .split_once(", ")
+            .ok_or_else(unexpected)?;
+        Ok((
+            parse_on_off(uplink).ok_or_else(unexpected)?,
+            parse_on_off(downlink).ok_or_else(unexpected)?,
+        ))
+    }
+
+    /// Turns the modem's duty cycle limiter on or off with `AT+LW=DC`. In regions such as EU868
+    /// transmitters must respect the regulatory duty cycle (typically 1% per sub-band), and the
+    /// limiter makes the modem hold back uplinks that would exceed it. Only turn it off for bench
+    /// testing or when duty cycle is enforced elsewhere: transmitting beyond the limit in the field
+    /// breaks the regional regulations. US915 and AU915 have no duty cycle, so this fails with
+    /// `Error::DutyCycleUnsupported` once either has been set with `set_region`.
+    pub fn set_duty_cycle(&mut self, enable: bool) -> Result {
+        const EXPECTED_PRELUDE: &str = "+LW: DC, ";
+        self.check_duty_cycle_region()?;
+        let cmd = format!("AT+LW=DC, {}", on_off(enable));
+        self.write_command(&cmd)?;
+        let n = self.read_until_break(self.command_timeout)?;
+        let response = self.framed_response(n, EXPECTED_PRELUDE)?;
+        if parse_duty_cycle(response) == Some(enable) {
+            Ok(())
+        } else {
+            Err(Error::unexpected_framed(
+                EXPECTED_PRELUDE,
+                on_off(enable),
+                response,
+            ))
+        }
+    }
+
+    /// Reads whether the duty cycle limiter is on
+    pub fn get_duty_cycle(&mut self) -> Result<bool> {
+        const EXPECTED_PRELUDE: &str = "+LW: DC, ";
+        self.check_duty_cycle_region()?;
+        let n = self.query("AT+LW=DC")?;
+        let response = self.framed_response(n, EXPECTED_PRELUDE)?;
+        parse_duty_cycle(response)
+            .ok_or_else(|| Error::unexpected_framed(EXPECTED_PRELUDE, "<ON|OFF>", response))
+    }
+
+    fn check_duty_cycle_region(&self) -> Result {
+        match self.region {
+            Some(region @ (Region::Us915 | Region::Au915)) => {
+                Err(Error::DutyCycleUnsupported(region))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Sets the battery level the modem reports in DevStatusAns, with `AT+LW=BAT`
+    pub fn set_battery_level(&mut self, level: u8) -> Result {
+        const EXPECTED_PRELUDE: &str = "+LW: BAT, ";
+        let cmd = format!("AT+LW=BAT, {level}");
+        self.write_command(&cmd)?;
+        let n = self.read_until_break(self.command_timeout)?;
+        self.check_framed_response(n, EXPECTED_PRELUDE, &level.to_string())
+    }
+
+    /// Reads the values the modem would report to a DevStatusReq. The modem doesn't expose the
+    /// margin it last sent, so it is derived from the last downlink seen by this handle; both
+    /// values reflect the last interaction rather than a fresh measurement.
+    pub fn get_device_status(&mut self) -> Result<DeviceStatus> {
+        const EXPECTED_PRELUDE: &str = "+LW: BAT, ";
+        let n = self.query("AT+LW=BAT")?;
+        let response = self.framed_response(n, EXPECTED_PRELUDE)?;
+        Ok(DeviceStatus {
+            battery: response.trim_end().parse().map_err(ParseError::from)?,
+            margin: self
+                .last_snr
+                .map(|snr| snr.round().clamp(-32.0, 31.0) as i8),
+        })
+    }
+
+    /// Reads the uplink and downlink frame counters with `AT+LW=ULDL` (`AT+LW=DC` is the duty
+    /// cycle setting). The format has varied between firmware versions, so a response that isn't
+    /// `+LW: ULDL, <uplink>, <downlink>` fails with `Error::FailedToParseFrameCounters`.
+    pub fn get_frame_counters(&mut self) -> Result<FrameCounters> {
+        const EXPECTED_PRELUDE: &str = "+LW: ULDL, ";
+        let n = self.query("AT+LW=ULDL")?;
+        let response = self.framed_response(n, EXPECTED_PRELUDE)?;
+        parse_frame_counters(response)
+    }
+
+    /// Overwrites the frame counters, for resynchronizing an ABP session with the network server
+    pub fn set_frame_counters(&mut self, counters: FrameCounters) -> Result {
+        const EXPECTED_PRELUDE: &str = "+LW: ULDL, ";
+        let FrameCounters { uplink, downlink } = counters;
+        let cmd = format!("AT+LW=ULDL, {uplink}, {downlink}");
+        self.write_command(&cmd)?;
+        let n = self.read_until_break(self.command_timeout)?;
+        self.check_framed_response(n, EXPECTED_PRELUDE, &format!("{uplink}, {downlink}"))
+    }
+}
+
+pub(crate) fn parse_frame_counters(response: &str) -> Result<FrameCounters> {
+    let failed = || Error::FailedToParseFrameCounters(response.to_string());
+    let (uplink, downlink) = response.trim_end().split_once(',').ok_or_else(failed)?;
+    Ok(FrameCounters {
+        uplink: uplink.trim().parse().map_err(|_| failed())?,
+        downlink: downlink.trim().parse().map_err(|_| failed())?,
+    })
+}
+
+/// Parses the state from a duty cycle response such as `ON` or `ON, 0`, where some firmware
+/// versions append the maximum duty cycle
+fn parse_duty_cycle(response: &str) -> Option<bool> {
+    parse_on_off(response.trim_end().split(',').next()?.trim())
+}
+
+fn on_off(enable: bool) -> &'static str {
+    if enable {
+        "ON"
+    } else {
+        "OFF"
+    }
+}
+
+fn parse_on_off(state: &str) -> Option<bool> {
+    match state {
+        "ON" => Some(true),
+        "OFF" => Some(false),
+        _ => None,
+    }
+}
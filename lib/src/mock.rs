@@ -0,0 +1,144 @@
+use crate::Transport;
+use std::{
+    collections::VecDeque,
+    io,
+    sync::{Arc, Mutex},
+};
+
+/// A scripted `Transport` that plays the part of the modem, for testing without hardware.
+///
+/// Each expected command is paired with the canned response the modem would send. Responses are
+/// read back a line at a time, the way the modem paces its output. Clones share the same script,
+/// so a test can keep one handle while the `LoraE5` owns another. Needs the `mock` feature.
+///
+/// ```
+/// use lora_e5::{LoraE5, MockPort};
+///
+/// let mock = MockPort::new();
+/// mock.expect("AT+PORT=3", "+PORT: 3\r\n");
+/// let mut lora_e5 = LoraE5::<64, _>::with_transport(mock.clone());
+/// lora_e5.set_port(3).unwrap();
+/// assert!(mock.is_done());
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct MockPort {
+    inner: Arc<Mutex<Script>>,
+}
+
+#[derive(Debug, Default)]
+struct Script {
+    expected: VecDeque<(Vec<u8>, Reply)>,
+    pending: VecDeque<u8>,
+    /// Error the next read fails with, ahead of any pending bytes
+    read_error: Option<io::ErrorKind>,
+    written: Vec<u8>,
+}
+
+#[derive(Debug)]
+enum Reply {
+    Response(Vec<u8>),
+    ReadError(io::ErrorKind),
+}
+
+impl MockPort {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Queues `command` (without its trailing newline or CRLF) as the next expected write,
+    /// answered by `response`
+    pub fn expect(&self, command: impl AsRef<[u8]>, response: impl AsRef<[u8]>) {
+        self.inner.lock().unwrap().expected.push_back((
+            command.as_ref().to_vec(),
+            Reply::Response(response.as_ref().to_vec()),
+        ));
+    }
+
+    /// Queues `command` as the next expected write, after which the next read fails with `kind`,
+    /// the way reads from a USB serial adapter fail once it has been unplugged
+    pub fn expect_read_error(&self, command: impl AsRef<[u8]>, kind: io::ErrorKind) {
+        self.inner
+            .lock()
+            .unwrap()
+            .expected
+            .push_back((command.as_ref().to_vec(), Reply::ReadError(kind)));
+    }
+
+    /// Makes `data` available to read without a command, like an unsolicited downlink
+    pub fn push_read(&self, data: impl AsRef<[u8]>) {
+        self.inner
+            .lock()
+            .unwrap()
+            .pending
+            .extend(data.as_ref().iter());
+    }
+
+    /// Everything written to the port so far
+    pub fn written(&self) -> Vec<u8> {
+        self.inner.lock().unwrap().written.clone()
+    }
+
+    /// Whether every expected command has been written and every response read
+    pub fn is_done(&self) -> bool {
+        let script = self.inner.lock().unwrap();
+        script.expected.is_empty() && script.pending.is_empty() && script.read_error.is_none()
+    }
+}
+
+impl Transport for MockPort {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut script = self.inner.lock().unwrap();
+        if let Some(kind) = script.read_error.take() {
+            return Err(kind.into());
+        }
+        let mut n = 0;
+        while n < buf.len() {
+            let Some(byte) = script.pending.pop_front() else {
+                break;
+            };
+            buf[n] = byte;
+            n += 1;
+            if byte == b'\n' {
+                break;
+            }
+        }
+        Ok(n)
+    }
+
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut script = self.inner.lock().unwrap();
+        script.written.extend_from_slice(buf);
+        let command = buf.strip_suffix(b"\n").unwrap_or(buf);
+        let command = command.strip_suffix(b"\r").unwrap_or(command);
+        match script.expected.pop_front() {
+            Some((expected, reply)) if expected == command => {
+                match reply {
+                    Reply::Response(response) => script.pending.extend(response),
+                    Reply::ReadError(kind) => script.read_error = Some(kind),
+                }
+                Ok(buf.len())
+            }
+            Some((expected, _)) => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "expected {:?}, got {:?}",
+                    String::from_utf8_lossy(&expected),
+                    String::from_utf8_lossy(command)
+                ),
+            )),
+            None => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("unexpected {:?}", String::from_utf8_lossy(command)),
+            )),
+        }
+    }
+
+    fn set_baud_rate(&mut self, _baud: u32) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn clear(&mut self) -> io::Result<()> {
+        self.inner.lock().unwrap().pending.clear();
+        Ok(())
+    }
+}
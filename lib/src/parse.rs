@@ -1,46 +1,191 @@
 use super::*;
 
-impl<const N: usize> LoraE5<N> {
+pub(crate) const READ_IDLE_SLEEP: Duration = Duration::from_millis(2);
+/// Reply to a command the modem can't take while an uplink or join is in progress
+pub(crate) const BUSY: &str = "LoRaWAN modem is busy";
+/// Reply to an uplink sent before joining
+pub(crate) const NOT_JOINED: &str = "Please join network first";
+
+/// Whether a failed read only means that no bytes arrived in time, as opposed to the port failing,
+/// such as when a USB serial adapter disappears
+pub(crate) fn is_idle_read(e: &io::Error) -> bool {
+    matches!(
+        e.kind(),
+        io::ErrorKind::TimedOut | io::ErrorKind::Interrupted
+    )
+}
+
+impl<const N: usize, T: Transport> LoraE5<N, T> {
     pub(crate) fn read_until_break(&mut self, timeout: Duration) -> Result<usize> {
         self.read_until_pattern(&["\n"], timeout)
     }
 
+    /// Writes a query and reads its one-line response. If the read times out or the port errors,
+    /// the command is sent again, up to the builder's `query_attempts` in total. Late output from
+    /// the failed attempt is drained first so it isn't taken for the new response. Only suitable
+    /// for commands that are safe to repeat.
+    pub(crate) fn query(&mut self, cmd: &str) -> Result<usize> {
+        let mut attempt = 1;
+        loop {
+            let result = self
+                .write_command(cmd)
+                .and_then(|()| self.read_until_break(self.command_timeout));
+            match result {
+                Err(Error::PartialResponse(_) | Error::Io(_)) if attempt < self.query_attempts => {
+                    debug!("retrying {cmd:?}");
+                    attempt += 1;
+                    self.drain(DRAIN_QUIET)?;
+                    self.clear_buffers()?;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Reads until the buffer ends with one of `patterns`, returning the number of bytes read.
+    /// Patterns are matched on the raw bytes, so a read that stops partway through a multi-byte
+    /// sequence, or a payload that isn't UTF-8 at all, doesn't interrupt it; callers validate the
+    /// response once it is complete.
     pub(crate) fn read_until_pattern(
         &mut self,
         patterns: &[&str],
         timeout: Duration,
+    ) -> Result<usize> {
+        let patterns: Vec<&[u8]> = patterns.iter().map(|pattern| pattern.as_bytes()).collect();
+        self.read_until_bytes(&patterns, timeout)
+    }
+
+    /// As `read_until_pattern`, with terminators that need not be text, for binary-safe reads
+    pub(crate) fn read_until_bytes(
+        &mut self,
+        patterns: &[&[u8]],
+        timeout: Duration,
     ) -> Result<usize> {
         let mut cursor = 0;
-        let mut time = time::Instant::now();
+        let start = time::Instant::now();
+        let mut time = start;
         loop {
-            if let Ok(n) = self.port.read(&mut self.buf[cursor..]) {
-                if n != 0 {
+            match self.port.read(&mut self.buf[cursor..]) {
+                // some platforms return immediately rather than blocking for the serial timeout,
+                // so back off briefly instead of spinning
+                Ok(0) => std::thread::sleep(READ_IDLE_SLEEP),
+                Ok(n) => {
+                    trace!(
+                        "read {:?}",
+                        redact_keys(&String::from_utf8_lossy(&self.buf[cursor..cursor + n]))
+                    );
                     cursor += n;
                     time = time::Instant::now();
                 }
+                Err(e) if is_idle_read(&e) => (),
+                Err(e) => return Err(e.into()),
             }
 
             for pattern in patterns {
-                if std::str::from_utf8(&self.buf[..cursor])?.ends_with(pattern) {
+                if self.buf[..cursor].ends_with(pattern) {
+                    debug!(
+                        "<- {:?} after {:?}",
+                        redact_keys(&String::from_utf8_lossy(&self.buf[..cursor])),
+                        start.elapsed()
+                    );
                     return Ok(cursor);
                 }
             }
 
+            if cursor == N {
+                return Err(Error::BufferFull(N));
+            }
+
             if time.elapsed() > timeout {
-                let partial_response = std::str::from_utf8(&self.buf[..cursor])?;
+                // the read may have stopped mid-character, which mustn't hide the timeout
+                let partial_response = String::from_utf8_lossy(&self.buf[..cursor]);
+                let partial_response = redact_keys(&partial_response);
+                debug!(
+                    "<- {partial_response:?} timed out after {:?}",
+                    start.elapsed()
+                );
                 return Err(Error::PartialResponse(partial_response.to_string()));
             }
         }
     }
 
+    /// Reads a response with no fixed terminator, such as a multi-line dump, by waiting up to
+    /// `timeout` for the first byte and then until the modem has been quiet for `quiet`.
+    #[cfg(any(
+        feature = "runtime",
+        feature = "sync",
+        all(test, feature = "serialport")
+    ))]
+    pub(crate) fn read_until_quiet(&mut self, quiet: Duration, timeout: Duration) -> Result<usize> {
+        let mut cursor = 0;
+        let start = time::Instant::now();
+        let mut time = start;
+        loop {
+            match self.port.read(&mut self.buf[cursor..]) {
+                Ok(0) => std::thread::sleep(READ_IDLE_SLEEP),
+                Ok(n) => {
+                    trace!(
+                        "read {:?}",
+                        redact_keys(&String::from_utf8_lossy(&self.buf[cursor..cursor + n]))
+                    );
+                    cursor += n;
+                    time = time::Instant::now();
+                }
+                Err(e) if is_idle_read(&e) => (),
+                Err(e) => return Err(e.into()),
+            }
+
+            if cursor > 0 && time.elapsed() > quiet {
+                debug!(
+                    "<- {:?} after {:?}",
+                    redact_keys(&String::from_utf8_lossy(&self.buf[..cursor])),
+                    start.elapsed()
+                );
+                return Ok(cursor);
+            }
+
+            if cursor == N {
+                return Err(Error::BufferFull(N));
+            }
+
+            if cursor == 0 && time.elapsed() > timeout {
+                return Err(Error::PartialResponse(String::new()));
+            }
+        }
+    }
+
+    /// Waits for the banner the modem prints when it restarts. The port may carry noise or partial
+    /// frames while the modem reboots, so bytes are scanned leniently rather than validated as
+    /// UTF-8 like a normal response.
+    pub(crate) fn wait_for_boot(&mut self, timeout: Duration) -> Result {
+        const BOOT_MARKERS: [&str; 3] = ["+AT", "+VER", "+RESET: OK"];
+        let start = time::Instant::now();
+        let mut received = Vec::new();
+        while start.elapsed() < timeout {
+            match self.port.read(&mut self.buf) {
+                Ok(0) => std::thread::sleep(READ_IDLE_SLEEP),
+                Ok(n) => {
+                    received.extend_from_slice(&self.buf[..n]);
+                    let received = String::from_utf8_lossy(&received);
+                    // only consider complete lines so the banner isn't matched mid-write
+                    if let Some((lines, _)) = received.rsplit_once('\n') {
+                        if BOOT_MARKERS.iter().any(|marker| lines.contains(marker)) {
+                            return Ok(());
+                        }
+                    }
+                }
+                Err(e) if is_idle_read(&e) => (),
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Err(Error::PartialResponse(
+            String::from_utf8_lossy(&received).to_string(),
+        ))
+    }
+
     pub(crate) fn framed_response(&mut self, n: usize, expected_prelude: &str) -> Result<&str> {
         let response = std::str::from_utf8(&self.buf[..n])?;
-        let (prelude, mode_response) = response.split_at(expected_prelude.len());
-        if prelude == expected_prelude {
-            Ok(mode_response)
-        } else {
-            Err(Error::UnexpectedResponse(response.to_string()))
-        }
+        framed_response(response, expected_prelude)
     }
 
     pub(crate) fn check_framed_response(
@@ -49,11 +194,67 @@ impl<const N: usize> LoraE5<N> {
         expected_prelude: &str,
         expected_response: &str,
     ) -> Result {
-        let response = self.framed_response(n, expected_prelude)?;
-        if response.trim_end() == expected_response {
-            Ok(())
-        } else {
-            Err(Error::UnexpectedResponse(response.to_string()))
+        let response = std::str::from_utf8(&self.buf[..n])?;
+        check_framed_response(response, expected_prelude, expected_response)
+    }
+}
+
+pub(crate) fn framed_response<'a>(response: &'a str, expected_prelude: &str) -> Result<&'a str> {
+    if let Some(error) = modem_error(response) {
+        return Err(error);
+    }
+    response
+        .strip_prefix(expected_prelude)
+        .ok_or_else(|| Error::unexpected(expected_prelude, response))
+}
+
+/// Recognizes the replies the modem gives instead of the expected response when it can't run a
+/// command: `ERROR(n)`, or being busy or not joined
+pub(crate) fn modem_error(response: &str) -> Option<Error> {
+    let (_, reply) = response.split_once(": ")?;
+    match reply.trim_end() {
+        BUSY => Some(Error::Busy),
+        NOT_JOINED => Some(Error::NotJoined),
+        reply => {
+            let code = reply
+                .strip_prefix("ERROR(")?
+                .strip_suffix(')')?
+                .parse()
+                .ok()?;
+            Some(Error::CommandError { code })
         }
     }
 }
+
+/// Describes the error codes the modem documents for `ERROR(n)` replies
+pub(crate) fn command_error_description(code: i32) -> &'static str {
+    match code {
+        -1 => "invalid parameter",
+        -10 => "unknown command",
+        -11 => "command in wrong format",
+        -12 => "command unavailable in current mode",
+        -20 => "too many parameters",
+        -21 => "command too long",
+        -22 => "timed out waiting for end of command",
+        -23 => "invalid character received",
+        -24 => "command too long, timed out or had an invalid character",
+        _ => "unknown error",
+    }
+}
+
+pub(crate) fn check_framed_response(
+    response: &str,
+    expected_prelude: &str,
+    expected_response: &str,
+) -> Result {
+    let response = framed_response(response, expected_prelude)?;
+    if response.trim_end() == expected_response {
+        Ok(())
+    } else {
+        Err(Error::unexpected_framed(
+            expected_prelude,
+            expected_response,
+            response,
+        ))
+    }
+}
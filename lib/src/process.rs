@@ -1,44 +1,114 @@
-use crate::{AppEui, Credentials, DevEui, Mode, Region, DR};
-use crate::{Downlink, Error as LoraE5Error, JoinResponse, LoraE5};
-use std::sync::{Arc, Mutex};
+use crate::request::{configuration, with_reconnect, Replay, Reply};
+use crate::{ActivationCredentials, AppEui, Class, DevAddr, DevEui, ProvisionConfig, Region, DR};
+use crate::{
+    DeviceStatus, Downlink, Error as LoraE5Error, JoinResponse, LinkCheck, LoraE5, ModemStatus,
+    SendReport, Transport, Unsolicited,
+};
+use std::{
+    fmt,
+    sync::{Arc, Mutex},
+};
+
+pub use crate::request::ReconnectPolicy;
 use tokio::{
-    sync::{mpsc, oneshot},
+    sync::{broadcast, mpsc, oneshot},
     task,
     time::Duration,
 };
 
 pub type Result<T = ()> = std::result::Result<T, Error>;
 
+/// Downlinks buffered per subscriber before the slowest one starts missing them
+const DOWNLINK_CAPACITY: usize = 16;
+/// Unsolicited lines buffered per subscriber before the slowest one starts missing them
+const EVENT_CAPACITY: usize = 16;
+/// How long the runtime listens for unsolicited output before checking for a request again
+const LISTEN_SLICE: Duration = Duration::from_millis(100);
+
+/// Answers the runtime's requests over tokio oneshot channels
 #[derive(Debug)]
-pub enum Request {
-    At(String, Duration, oneshot::Sender<Result<String>>),
-    Join(bool, oneshot::Sender<Result<JoinResponse>>),
-    Configure(Credentials, oneshot::Sender<Result>),
-    GetAppEui(oneshot::Sender<Result<AppEui>>),
-    GetDevEui(oneshot::Sender<Result<DevEui>>),
-    DataRate(DR, oneshot::Sender<Result>),
-    Region(Region, oneshot::Sender<Result>),
-    Shutdown,
-    SendData(Vec<u8>, u8, bool, oneshot::Sender<Result<Option<Downlink>>>),
-    SendAscii(String, u8, bool, oneshot::Sender<Result<Option<Downlink>>>),
+pub struct Oneshot;
+
+impl Reply for Oneshot {
+    type Sender<T: fmt::Debug + Send + 'static> = oneshot::Sender<Result<T>>;
+
+    fn send<T: fmt::Debug + Send + 'static>(sender: Self::Sender<T>, result: crate::Result<T>) {
+        let _ = sender.send(result.map_err(|e| e.into()));
+    }
 }
 
+pub type Request = crate::request::Request<Oneshot>;
+
+#[derive(Clone)]
 pub struct Client {
     sender: mpsc::Sender<Request>,
+    downlinks: broadcast::Sender<Downlink>,
+    events: broadcast::Sender<String>,
 }
 
 impl Client {
+    /// Subscribes to unsolicited downlinks, such as those a Class C device receives outside of an
+    /// uplink's receive windows. While anyone is subscribed the runtime listens for downlinks
+    /// whenever it is idle, which can delay each request by up to 100ms. Downlinks answering an
+    /// uplink are still returned by `send` rather than broadcast here. Switching the modem to
+    /// Class C (`AT+CLASS=C`) is up to the caller.
+    pub fn downlinks(&self) -> broadcast::Receiver<Downlink> {
+        self.downlinks.subscribe()
+    }
+
+    /// Subscribes to lines the modem prints unprompted, such as its boot banner after a power
+    /// glitch or `+EVT:` lines. The runtime clears such output before each request so it isn't
+    /// taken for the response, and reports it here; while anyone is subscribed it also listens
+    /// whenever it is idle, as it does for `downlinks`.
+    pub fn events(&self) -> broadcast::Receiver<String> {
+        self.events.subscribe()
+    }
+
     pub async fn at_command(&self, cmd: &str, timeout: Duration) -> Result<String> {
         let (tx, rx) = oneshot::channel();
-        let mut cmd = cmd.to_string();
-        cmd.push('\n');
-        self.sender.send(Request::At(cmd, timeout, tx)).await?;
+        self.sender
+            .send(Request::At(cmd.to_string(), timeout, tx))
+            .await?;
+        rx.await?
+    }
+
+    /// Sends a raw AT command and returns every line of the response, which is considered
+    /// complete once the modem has been quiet for `quiet`. Useful for dumps such as `AT+CH`.
+    pub async fn at_command_multiline(&self, cmd: &str, quiet: Duration) -> Result<String> {
+        let (tx, rx) = oneshot::channel();
+        self.sender
+            .send(Request::AtMultiline(cmd.to_string(), quiet, tx))
+            .await?;
         rx.await?
     }
 
     pub async fn join(&self, force: bool) -> Result<JoinResponse> {
         let (tx, rx) = oneshot::channel();
-        self.sender.send(Request::Join(force, tx)).await?;
+        self.sender.send(Request::Join(force, None, tx)).await?;
+        rx.await?
+    }
+
+    /// Joins, waiting up to `timeout` for the join to finish rather than the default 20 seconds
+    pub async fn join_with_timeout(&self, force: bool, timeout: Duration) -> Result<JoinResponse> {
+        let (tx, rx) = oneshot::channel();
+        self.sender
+            .send(Request::Join(force, Some(timeout), tx))
+            .await?;
+        rx.await?
+    }
+
+    /// Joins, trying again after `backoff` when the join fails, up to `attempts` tries in total.
+    /// The runtime serves no other requests until the last attempt finishes.
+    pub async fn join_with_retries(
+        &self,
+        force: bool,
+        attempts: u8,
+        backoff: Duration,
+    ) -> Result<JoinResponse> {
+        let (tx, rx) = oneshot::channel();
+        self.sender
+            .send(Request::JoinWithRetries(force, attempts, backoff, tx))
+            .await?;
         rx.await?
     }
 
@@ -48,17 +118,107 @@ impl Client {
         rx.await?
     }
 
+    /// Restricts a US915/AU915 modem to the eight channels of `subband` (1-8)
+    pub async fn subband(&self, subband: u8) -> Result {
+        let (tx, rx) = oneshot::channel();
+        self.sender.send(Request::Subband(subband, tx)).await?;
+        rx.await?
+    }
+
     pub async fn data_rate(&self, dr: DR) -> Result {
         let (tx, rx) = oneshot::channel();
         self.sender.send(Request::DataRate(dr, tx)).await?;
         rx.await?
     }
 
-    pub async fn configure(&self, credentials: Credentials) -> Result {
+    /// Reads the current data rate, which ADR may have changed
+    pub async fn get_data_rate(&self) -> Result<DR> {
         let (tx, rx) = oneshot::channel();
-        self.sender
-            .send(Request::Configure(credentials, tx))
-            .await?;
+        self.sender.send(Request::GetDataRate(tx)).await?;
+        rx.await?
+    }
+
+    pub async fn tx_power(&self, dbm: u8) -> Result<u8> {
+        let (tx, rx) = oneshot::channel();
+        self.sender.send(Request::TxPower(dbm, tx)).await?;
+        rx.await?
+    }
+
+    pub async fn get_tx_power(&self) -> Result<u8> {
+        let (tx, rx) = oneshot::channel();
+        self.sender.send(Request::GetTxPower(tx)).await?;
+        rx.await?
+    }
+
+    pub async fn get_vdd(&self) -> Result<f32> {
+        let (tx, rx) = oneshot::channel();
+        self.sender.send(Request::GetVdd(tx)).await?;
+        rx.await?
+    }
+
+    /// Reads the modem's internal temperature in Celsius
+    pub async fn get_temperature(&self) -> Result<f32> {
+        let (tx, rx) = oneshot::channel();
+        self.sender.send(Request::GetTemperature(tx)).await?;
+        rx.await?
+    }
+
+    /// Reads the battery level and margin the modem reports to a DevStatusReq
+    pub async fn get_device_status(&self) -> Result<DeviceStatus> {
+        let (tx, rx) = oneshot::channel();
+        self.sender.send(Request::GetDeviceStatus(tx)).await?;
+        rx.await?
+    }
+
+    pub async fn class(&self, class: Class) -> Result {
+        let (tx, rx) = oneshot::channel();
+        self.sender.send(Request::Class(class, tx)).await?;
+        rx.await?
+    }
+
+    pub async fn get_class(&self) -> Result<Class> {
+        let (tx, rx) = oneshot::channel();
+        self.sender.send(Request::GetClass(tx)).await?;
+        rx.await?
+    }
+
+    /// Reads a snapshot of the modem's configuration and session, as `LoraE5::status`
+    pub async fn status(&self) -> Result<ModemStatus> {
+        let (tx, rx) = oneshot::channel();
+        self.sender.send(Request::Status(tx)).await?;
+        rx.await?
+    }
+
+    pub async fn adr(&self, enable: bool) -> Result {
+        let (tx, rx) = oneshot::channel();
+        self.sender.send(Request::Adr(enable, tx)).await?;
+        rx.await?
+    }
+
+    pub async fn get_adr(&self) -> Result<bool> {
+        let (tx, rx) = oneshot::channel();
+        self.sender.send(Request::GetAdr(tx)).await?;
+        rx.await?
+    }
+
+    /// Sets the activation mode and credentials for `region`, restricting a US915/AU915 modem to
+    /// `subband` if given
+    pub async fn configure(
+        &self,
+        credentials: impl Into<ActivationCredentials>,
+        region: Region,
+        subband: Option<u8>,
+    ) -> Result {
+        let config = configuration(credentials, region, subband);
+        let (tx, rx) = oneshot::channel();
+        self.sender.send(Request::Configure(config, tx)).await?;
+        rx.await?
+    }
+
+    /// Configures the modem and joins, as `LoraE5::provision`
+    pub async fn provision(&self, config: ProvisionConfig) -> Result<JoinResponse> {
+        let (tx, rx) = oneshot::channel();
+        self.sender.send(Request::Provision(config, tx)).await?;
         rx.await?
     }
 
@@ -74,26 +234,105 @@ impl Client {
         rx.await?
     }
 
-    pub async fn send(&self, data: Vec<u8>, port: u8, confirmed: bool) -> Result<Option<Downlink>> {
+    pub async fn get_dev_addr(&self) -> Result<DevAddr> {
+        let (tx, rx) = oneshot::channel();
+        self.sender.send(Request::GetDevAddr(tx)).await?;
+        rx.await?
+    }
+
+    pub async fn send(&self, data: Vec<u8>, port: u8, confirmed: bool) -> Result<SendReport> {
+        let (tx, rx) = oneshot::channel();
+        self.sender
+            .send(Request::SendData(data, port, confirmed, None, tx))
+            .await?;
+        rx.await?
+    }
+
+    pub async fn send_with_timeout(
+        &self,
+        data: Vec<u8>,
+        port: u8,
+        confirmed: bool,
+        timeout: Duration,
+    ) -> Result<SendReport> {
+        let (tx, rx) = oneshot::channel();
+        self.sender
+            .send(Request::SendData(data, port, confirmed, Some(timeout), tx))
+            .await?;
+        rx.await?
+    }
+
+    pub async fn send_ascii(&self, data: String, port: u8, confirmed: bool) -> Result<SendReport> {
         let (tx, rx) = oneshot::channel();
         self.sender
-            .send(Request::SendData(data, port, confirmed, tx))
+            .send(Request::SendAscii(data, port, confirmed, None, tx))
             .await?;
         rx.await?
     }
-    pub async fn send_ascii(
+
+    pub async fn send_ascii_with_timeout(
         &self,
         data: String,
         port: u8,
         confirmed: bool,
-    ) -> Result<Option<Downlink>> {
+        timeout: Duration,
+    ) -> Result<SendReport> {
         let (tx, rx) = oneshot::channel();
         self.sender
-            .send(Request::SendAscii(data, port, confirmed, tx))
+            .send(Request::SendAscii(data, port, confirmed, Some(timeout), tx))
             .await?;
         rx.await?
     }
 
+    /// Waits up to `timeout` for an unsolicited downlink (eg: Class C)
+    pub async fn poll_downlink(&self, timeout: Duration) -> Result<Option<Downlink>> {
+        let (tx, rx) = oneshot::channel();
+        self.sender.send(Request::PollDownlink(timeout, tx)).await?;
+        rx.await?
+    }
+
+    /// Puts the modem into low power mode. Other requests will fail until `wake` is called.
+    pub async fn sleep(&self) -> Result {
+        let (tx, rx) = oneshot::channel();
+        self.sender.send(Request::Sleep(tx)).await?;
+        rx.await?
+    }
+
+    pub async fn wake(&self) -> Result {
+        let (tx, rx) = oneshot::channel();
+        self.sender.send(Request::Wake(tx)).await?;
+        rx.await?
+    }
+
+    /// Restarts the modem, using `AT+RESET` if `soft` is set and `ATZ` otherwise
+    pub async fn reset(&self, soft: bool) -> Result {
+        let (tx, rx) = oneshot::channel();
+        self.sender.send(Request::Reset(soft, tx)).await?;
+        rx.await?
+    }
+
+    /// Restores factory defaults. Credentials and region must be configured again afterwards.
+    pub async fn factory_reset(&self) -> Result {
+        let (tx, rx) = oneshot::channel();
+        self.sender.send(Request::FactoryReset(tx)).await?;
+        rx.await?
+    }
+
+    /// Runs a link check, sending an empty uplink on `port` to carry the request
+    pub async fn link_check(&self, port: u8) -> Result<LinkCheck> {
+        let (tx, rx) = oneshot::channel();
+        self.sender.send(Request::LinkCheck(port, tx)).await?;
+        rx.await?
+    }
+
+    /// Runs `(command, expected_prelude)` pairs in order, stopping at the first failure; see
+    /// `LoraE5::run_script`
+    pub async fn run_script(&self, commands: Vec<(String, String)>) -> Result<Vec<String>> {
+        let (tx, rx) = oneshot::channel();
+        self.sender.send(Request::Script(commands, tx)).await?;
+        rx.await?
+    }
+
     pub async fn send_shutdown(&self) -> Result {
         Ok(self.sender.send(Request::Shutdown).await?)
     }
@@ -102,6 +341,9 @@ impl Client {
 pub struct Setup {
     sender: mpsc::Sender<Request>,
     receiver: mpsc::Receiver<Request>,
+    downlinks: broadcast::Sender<Downlink>,
+    events: broadcast::Sender<String>,
+    reconnect: Option<ReconnectPolicy>,
 }
 
 impl Default for Setup {
@@ -113,126 +355,162 @@ impl Default for Setup {
 impl Setup {
     pub fn new<const C: usize>() -> Self {
         let (sender, receiver) = mpsc::channel(C);
-        Self { sender, receiver }
+        let (downlinks, _) = broadcast::channel(DOWNLINK_CAPACITY);
+        let (events, _) = broadcast::channel(EVENT_CAPACITY);
+        Self {
+            sender,
+            receiver,
+            downlinks,
+            events,
+            reconnect: None,
+        }
+    }
+
+    /// Reopens the port when a request fails with an I/O error, and retries the request if it is
+    /// safe to repeat. Uplinks, joins, resets and raw commands aren't retried, since the modem may
+    /// already have acted on them; they fail with the original error once the port is back. The
+    /// modem must have been opened with `open_usb`, `open_by_serial` or `open_path` for this to
+    /// take effect.
+    pub fn reconnect(mut self, policy: ReconnectPolicy) -> Self {
+        self.reconnect = Some(policy);
+        self
     }
 
     pub fn get_client(&self) -> Client {
         Client {
             sender: self.sender.clone(),
+            downlinks: self.downlinks.clone(),
+            events: self.events.clone(),
         }
     }
 
     pub fn complete(self) -> Runtime {
         Runtime {
             receiver: self.receiver,
+            downlinks: self.downlinks,
+            events: self.events,
+            reconnect: self.reconnect,
         }
     }
 }
 
 pub struct Runtime {
     receiver: mpsc::Receiver<Request>,
+    downlinks: broadcast::Sender<Downlink>,
+    events: broadcast::Sender<String>,
+    reconnect: Option<ReconnectPolicy>,
 }
 
-fn respond<T>(response_sender: oneshot::Sender<Result<T>>, response: Result<T>) -> Result {
-    let _ = response_sender.send(response);
-    Ok(())
+/// Sets up a runtime for `lora_e5` and spawns it onto the current tokio runtime.
+///
+/// Each modem gets its own runtime and `Client`, so several dongles can be driven from one
+/// process by spawning one per device and keeping the clients in a map keyed by device:
+///
+/// ```no_run
+/// # async fn example() -> lora_e5::process::Result {
+/// use lora_e5::{process, LoraE5};
+/// use std::collections::HashMap;
+///
+/// let mut devices = HashMap::new();
+/// for path in ["/dev/ttyUSB0", "/dev/ttyUSB1"] {
+///     let (client, _handle) = process::spawn(LoraE5::<128>::open_path(path)?);
+///     devices.insert(path, client);
+/// }
+/// let dev_eui = devices["/dev/ttyUSB1"].get_dev_eui().await?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn spawn<const N: usize, T>(lora_e5: LoraE5<N, T>) -> (Client, task::JoinHandle<Result>)
+where
+    T: Transport + Send + 'static,
+{
+    let setup = Setup::default();
+    let client = setup.get_client();
+    let handle = tokio::spawn(setup.complete().run(lora_e5));
+    (client, handle)
 }
 
 impl Runtime {
-    pub async fn run<const N: usize>(mut self, lora_e5: LoraE5<N>) -> Result {
-        let lora_e5 = Arc::new(Mutex::new(lora_e5));
-        while let Some(request) = self.receiver.recv().await {
-            let lora_e5 = lora_e5.clone();
-            match request {
-                Request::At(cmd, timeout, sender) => {
-                    let response = task::spawn_blocking(move || {
-                        let mut lora_e5 = lora_e5.lock().unwrap();
-                        lora_e5.write_command(&cmd)?;
-                        let n = lora_e5.read_until_break(timeout)?;
-                        Ok(std::str::from_utf8(&lora_e5.buf[..n])?.to_string())
-                    })
-                    .await?;
-                    respond(sender, response)?;
-                }
-                Request::Configure(credentials, response_sender) => {
-                    let result = task::spawn_blocking(move || {
-                        let mut lora_e5 = lora_e5.lock().unwrap();
-                        lora_e5.set_mode(Mode::Otaa)?;
-                        lora_e5.set_region(Region::Us915)?;
-                        lora_e5.set_credentials(&credentials)?;
-                        lora_e5.subband2_only()?;
-                        Ok(())
-                    })
-                    .await?;
-                    respond(response_sender, result)?;
-                }
-                Request::GetAppEui(response_sender) => {
-                    let result = task::spawn_blocking(move || {
-                        let mut lora_e5 = lora_e5.lock().unwrap();
-                        Ok(lora_e5.get_app_eui()?)
-                    })
-                    .await?;
-                    respond(response_sender, result)?;
-                }
-                Request::GetDevEui(response_sender) => {
-                    let result = task::spawn_blocking(move || {
-                        let mut lora_e5 = lora_e5.lock().unwrap();
-                        Ok(lora_e5.get_dev_eui()?)
-                    })
-                    .await?;
-                    respond(response_sender, result)?;
-                }
-                Request::Join(force, sender) => {
-                    let result = task::spawn_blocking(move || {
-                        let mut lora_e5 = lora_e5.lock().unwrap();
-                        if force {
-                            lora_e5.force_join()
-                        } else {
-                            lora_e5.join()
+    /// Waits for the next request. While a client is subscribed to downlinks or events, the
+    /// modem is listened to in between checking for requests. Requests and listening both go
+    /// through the modem's lock one at a time, so listening never reads a command's response.
+    /// Output left over from before the request is cleared before it is returned.
+    async fn next_request<const N: usize, T>(
+        &mut self,
+        lora_e5: &Arc<Mutex<LoraE5<N, T>>>,
+    ) -> Result<Option<Request>>
+    where
+        T: Transport + Send + 'static,
+    {
+        loop {
+            let request =
+                if self.downlinks.receiver_count() == 0 && self.events.receiver_count() == 0 {
+                    self.receiver.recv().await
+                } else {
+                    match self.receiver.try_recv() {
+                        Ok(request) => Some(request),
+                        Err(mpsc::error::TryRecvError::Disconnected) => None,
+                        Err(mpsc::error::TryRecvError::Empty) => {
+                            self.listen(lora_e5, LISTEN_SLICE).await?;
+                            continue;
                         }
-                    })
-                    .await?;
-                    respond(sender, result.map_err(|e| e.into()))?;
-                }
-                Request::DataRate(dr, sender) => {
-                    let result = task::spawn_blocking(move || {
-                        let mut lora_e5 = lora_e5.lock().unwrap();
-                        lora_e5.set_datarate(dr)
-                    })
-                    .await?;
-                    respond(sender, result.map_err(|e| e.into()))?;
-                }
-                Request::Region(region, sender) => {
-                    let result = task::spawn_blocking(move || {
-                        let mut lora_e5 = lora_e5.lock().unwrap();
-                        lora_e5.set_region(region)
-                    })
-                    .await?;
-                    respond(sender, result.map_err(|e| e.into()))?;
-                }
-                Request::SendData(data, port, confirmed, sender) => {
-                    let result = task::spawn_blocking(move || {
-                        let mut lora_e5 = lora_e5.lock().unwrap();
-                        lora_e5.send(&data, port, confirmed)
-                    })
-                    .await?;
-                    respond(sender, result.map_err(|e| e.into()))?;
-                }
-                Request::SendAscii(data, port, confirmed, sender) => {
-                    let result = task::spawn_blocking(move || {
-                        let mut lora_e5 = lora_e5.lock().unwrap();
-                        lora_e5.send_ascii(&data, port, confirmed)
-                    })
-                    .await?;
-                    respond(sender, result.map_err(|e| e.into()))?;
+                    }
+                };
+            if request.is_some() {
+                self.listen(lora_e5, Duration::ZERO).await?;
+            }
+            return Ok(request);
+        }
+    }
+
+    /// Reads whatever the modem prints unprompted within `timeout` and broadcasts it
+    async fn listen<const N: usize, T>(
+        &mut self,
+        lora_e5: &Arc<Mutex<LoraE5<N, T>>>,
+        timeout: Duration,
+    ) -> Result
+    where
+        T: Transport + Send + 'static,
+    {
+        let lora_e5 = lora_e5.clone();
+        let reconnect = self.reconnect;
+        let result = task::spawn_blocking(move || {
+            let mut lora_e5 = lora_e5.lock().unwrap();
+            with_reconnect(&mut lora_e5, reconnect, Replay::Yes, |lora_e5| {
+                lora_e5.read_unsolicited(timeout)
+            })
+        })
+        .await?;
+        // a port that fails here fails the next request too, which reports it
+        for unsolicited in result.unwrap_or_default() {
+            match unsolicited {
+                Unsolicited::Downlink(downlink) => {
+                    let _ = self.downlinks.send(downlink);
                 }
-                Request::Shutdown => {
-                    return Ok(());
+                Unsolicited::Line(line) => {
+                    let _ = self.events.send(line);
                 }
             }
         }
         Ok(())
     }
+
+    pub async fn run<const N: usize, T>(mut self, lora_e5: LoraE5<N, T>) -> Result
+    where
+        T: Transport + Send + 'static,
+    {
+        let lora_e5 = Arc::new(Mutex::new(lora_e5));
+        let reconnect = self.reconnect;
+        while let Some(request) = self.next_request(&lora_e5).await? {
+            if let Request::Shutdown = request {
+                return Ok(());
+            }
+            let lora_e5 = lora_e5.clone();
+            task::spawn_blocking(move || request.handle(&mut lora_e5.lock().unwrap(), reconnect))
+                .await?;
+        }
+        Ok(())
+    }
 }
 
 use thiserror::Error;
@@ -0,0 +1,85 @@
+use super::*;
+
+/// Everything needed to take a modem from any state to joined, for `LoraE5::provision`.
+/// The activation mode follows from the credentials.
+#[derive(Debug, Clone)]
+pub struct ProvisionConfig {
+    pub region: Region,
+    pub credentials: ActivationCredentials,
+    /// US915/AU915 subband (1-8) to restrict the modem to
+    pub subband: Option<u8>,
+    pub datarate: Option<DR>,
+    /// Join even if the modem already has a session
+    pub force_join: bool,
+}
+
+impl ProvisionConfig {
+    pub fn new(region: Region, credentials: impl Into<ActivationCredentials>) -> Self {
+        Self {
+            region,
+            credentials: credentials.into(),
+            subband: None,
+            datarate: None,
+            force_join: false,
+        }
+    }
+
+    pub fn subband(mut self, subband: u8) -> Self {
+        self.subband = Some(subband);
+        self
+    }
+
+    pub fn datarate(mut self, dr: DR) -> Self {
+        self.datarate = Some(dr);
+        self
+    }
+
+    pub fn force_join(mut self, force: bool) -> Self {
+        self.force_join = force;
+        self
+    }
+}
+
+impl<const N: usize, T: Transport> LoraE5<N, T> {
+    /// Configures the modem from `config` and joins the network. ABP devices have their session
+    /// keys configured up front, so they skip the join and report `AlreadyJoined`. Errors name
+    /// the step that failed.
+    pub fn provision(&mut self, config: &ProvisionConfig) -> Result<JoinResponse> {
+        self.configure(config)?;
+        let join = match config.credentials {
+            ActivationCredentials::Abp(_) => return Ok(JoinResponse::AlreadyJoined),
+            ActivationCredentials::Otaa(_) if config.force_join => self.force_join(),
+            ActivationCredentials::Otaa(_) => self.join(),
+        };
+        join.map_err(|e| e.context("join"))
+    }
+
+    /// Runs every step of `provision` except the join: mode, region, credentials, then the
+    /// optional subband and data rate
+    pub fn configure(&mut self, config: &ProvisionConfig) -> Result {
+        let mode = match config.credentials {
+            ActivationCredentials::Otaa(_) => Mode::Otaa,
+            ActivationCredentials::Abp(_) => Mode::Abp,
+        };
+        self.set_mode(mode).map_err(|e| e.context("set mode"))?;
+        self.set_region(config.region)
+            .map_err(|e| e.context("set region"))?;
+        match &config.credentials {
+            ActivationCredentials::Otaa(credentials) => self.set_credentials(credentials),
+            ActivationCredentials::Abp(credentials) => self.set_abp_credentials(credentials),
+        }
+        .map_err(|e| e.context("set credentials"))?;
+        if let Some(subband) = config.subband {
+            match config.region {
+                Region::Us915 | Region::Au915 => self.set_subband(subband),
+                region => Err(Error::SubbandUnsupported(region)),
+            }
+            .map_err(|e| e.context("set subband"))?;
+        }
+        if let Some(dr) = config.datarate {
+            self.set_datarate(dr)
+                .map_err(|e| e.context("set data rate"))?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,344 @@
+//! The requests a worker serializes onto the modem, shared by the tokio-based `process` runtime
+//! and the thread-based `sync` worker. Both define their requests as `Request<R>` with their own
+//! reply channel, so a request added here is served by both.
+use crate::{
+    ActivationCredentials, AppEui, Class, DevAddr, DevEui, DeviceStatus, Downlink, Error,
+    JoinResponse, LinkCheck, LoraE5, ModemStatus, ProvisionConfig, Region, SendReport, Transport,
+    DR,
+};
+use std::{fmt, time::Duration};
+
+/// How a worker answers its requests
+pub trait Reply {
+    /// The channel a request's result is sent back on
+    type Sender<T: fmt::Debug + Send + 'static>: fmt::Debug + Send;
+
+    fn send<T: fmt::Debug + Send + 'static>(sender: Self::Sender<T>, result: crate::Result<T>);
+}
+
+#[derive(Debug)]
+pub enum Request<R: Reply> {
+    At(String, Duration, R::Sender<String>),
+    AtMultiline(String, Duration, R::Sender<String>),
+    Join(bool, Option<Duration>, R::Sender<JoinResponse>),
+    JoinWithRetries(bool, u8, Duration, R::Sender<JoinResponse>),
+    Configure(ProvisionConfig, R::Sender<()>),
+    Provision(ProvisionConfig, R::Sender<JoinResponse>),
+    GetAppEui(R::Sender<AppEui>),
+    GetDevEui(R::Sender<DevEui>),
+    GetDevAddr(R::Sender<DevAddr>),
+    DataRate(DR, R::Sender<()>),
+    GetDataRate(R::Sender<DR>),
+    Region(Region, R::Sender<()>),
+    Subband(u8, R::Sender<()>),
+    TxPower(u8, R::Sender<u8>),
+    GetTxPower(R::Sender<u8>),
+    GetVdd(R::Sender<f32>),
+    GetTemperature(R::Sender<f32>),
+    GetDeviceStatus(R::Sender<DeviceStatus>),
+    Status(R::Sender<ModemStatus>),
+    Class(Class, R::Sender<()>),
+    GetClass(R::Sender<Class>),
+    Adr(bool, R::Sender<()>),
+    GetAdr(R::Sender<bool>),
+    Sleep(R::Sender<()>),
+    Wake(R::Sender<()>),
+    Reset(bool, R::Sender<()>),
+    FactoryReset(R::Sender<()>),
+    Shutdown,
+    SendData(Vec<u8>, u8, bool, Option<Duration>, R::Sender<SendReport>),
+    SendAscii(String, u8, bool, Option<Duration>, R::Sender<SendReport>),
+    PollDownlink(Duration, R::Sender<Option<Downlink>>),
+    LinkCheck(u8, R::Sender<LinkCheck>),
+    Script(Vec<(String, String)>, R::Sender<Vec<String>>),
+}
+
+/// Builds the configuration `configure` applies, restricting a US915/AU915 modem to `subband` if
+/// given
+pub(crate) fn configuration(
+    credentials: impl Into<ActivationCredentials>,
+    region: Region,
+    subband: Option<u8>,
+) -> ProvisionConfig {
+    let mut config = ProvisionConfig::new(region, credentials);
+    config.subband = subband;
+    config
+}
+
+impl<R: Reply> Request<R> {
+    /// Runs the request against the modem and sends back its result, reconnecting as `reconnect`
+    /// allows if the port fails. Workers stop on `Shutdown` rather than handling it.
+    pub(crate) fn handle<const N: usize, T: Transport>(
+        self,
+        lora_e5: &mut LoraE5<N, T>,
+        reconnect: Option<ReconnectPolicy>,
+    ) {
+        use Replay::{No, Yes};
+        match self {
+            Request::At(cmd, timeout, sender) => {
+                let result = with_reconnect(lora_e5, reconnect, No, |lora_e5| {
+                    lora_e5.write_raw_command(&cmd)?;
+                    let n = lora_e5.read_until_break(timeout)?;
+                    Ok(std::str::from_utf8(&lora_e5.buf[..n])?.to_string())
+                });
+                R::send(sender, result);
+            }
+            Request::AtMultiline(cmd, quiet, sender) => {
+                let result = with_reconnect(lora_e5, reconnect, No, |lora_e5| {
+                    lora_e5.write_raw_command(&cmd)?;
+                    let timeout = lora_e5.command_timeout;
+                    let n = lora_e5.read_until_quiet(quiet, timeout)?;
+                    Ok(std::str::from_utf8(&lora_e5.buf[..n])?.to_string())
+                });
+                R::send(sender, result);
+            }
+            Request::Join(force, timeout, sender) => {
+                let result = with_reconnect(lora_e5, reconnect, No, |lora_e5| match timeout {
+                    Some(timeout) => lora_e5.join_with_timeout(force, timeout),
+                    None if force => lora_e5.force_join(),
+                    None => lora_e5.join(),
+                });
+                R::send(sender, result);
+            }
+            Request::JoinWithRetries(force, attempts, backoff, sender) => {
+                let result = with_reconnect(lora_e5, reconnect, No, |lora_e5| {
+                    lora_e5.join_with_retries(force, attempts, backoff)
+                });
+                R::send(sender, result);
+            }
+            Request::Configure(config, sender) => {
+                let result = with_reconnect(lora_e5, reconnect, Yes, |lora_e5| {
+                    lora_e5.configure(&config)
+                });
+                R::send(sender, result);
+            }
+            Request::Provision(config, sender) => {
+                let result =
+                    with_reconnect(lora_e5, reconnect, No, |lora_e5| lora_e5.provision(&config));
+                R::send(sender, result);
+            }
+            Request::GetAppEui(sender) => {
+                let result =
+                    with_reconnect(lora_e5, reconnect, Yes, |lora_e5| lora_e5.get_app_eui());
+                R::send(sender, result);
+            }
+            Request::GetDevEui(sender) => {
+                let result =
+                    with_reconnect(lora_e5, reconnect, Yes, |lora_e5| lora_e5.get_dev_eui());
+                R::send(sender, result);
+            }
+            Request::GetDevAddr(sender) => {
+                let result =
+                    with_reconnect(lora_e5, reconnect, Yes, |lora_e5| lora_e5.get_dev_addr());
+                R::send(sender, result);
+            }
+            Request::DataRate(dr, sender) => {
+                let result =
+                    with_reconnect(lora_e5, reconnect, Yes, |lora_e5| lora_e5.set_datarate(dr));
+                R::send(sender, result);
+            }
+            Request::GetDataRate(sender) => {
+                let result =
+                    with_reconnect(lora_e5, reconnect, Yes, |lora_e5| lora_e5.get_datarate());
+                R::send(sender, result);
+            }
+            Request::Region(region, sender) => {
+                let result = with_reconnect(lora_e5, reconnect, Yes, |lora_e5| {
+                    lora_e5.set_region(region)
+                });
+                R::send(sender, result);
+            }
+            Request::Subband(subband, sender) => {
+                let result = with_reconnect(lora_e5, reconnect, Yes, |lora_e5| {
+                    lora_e5.set_subband(subband)
+                });
+                R::send(sender, result);
+            }
+            Request::TxPower(dbm, sender) => {
+                let result =
+                    with_reconnect(lora_e5, reconnect, Yes, |lora_e5| lora_e5.set_tx_power(dbm));
+                R::send(sender, result);
+            }
+            Request::GetTxPower(sender) => {
+                let result =
+                    with_reconnect(lora_e5, reconnect, Yes, |lora_e5| lora_e5.get_tx_power());
+                R::send(sender, result);
+            }
+            Request::GetVdd(sender) => {
+                let result = with_reconnect(lora_e5, reconnect, Yes, |lora_e5| lora_e5.get_vdd());
+                R::send(sender, result);
+            }
+            Request::GetTemperature(sender) => {
+                let result =
+                    with_reconnect(lora_e5, reconnect, Yes, |lora_e5| lora_e5.get_temperature());
+                R::send(sender, result);
+            }
+            Request::GetDeviceStatus(sender) => {
+                let result = with_reconnect(lora_e5, reconnect, Yes, |lora_e5| {
+                    lora_e5.get_device_status()
+                });
+                R::send(sender, result);
+            }
+            Request::Status(sender) => {
+                let result = with_reconnect(lora_e5, reconnect, Yes, |lora_e5| lora_e5.status());
+                R::send(sender, result);
+            }
+            Request::Class(class, sender) => {
+                let result =
+                    with_reconnect(lora_e5, reconnect, Yes, |lora_e5| lora_e5.set_class(class));
+                R::send(sender, result);
+            }
+            Request::GetClass(sender) => {
+                let result = with_reconnect(lora_e5, reconnect, Yes, |lora_e5| lora_e5.get_class());
+                R::send(sender, result);
+            }
+            Request::Adr(enable, sender) => {
+                let result =
+                    with_reconnect(lora_e5, reconnect, Yes, |lora_e5| lora_e5.set_adr(enable));
+                R::send(sender, result);
+            }
+            Request::GetAdr(sender) => {
+                let result = with_reconnect(lora_e5, reconnect, Yes, |lora_e5| lora_e5.get_adr());
+                R::send(sender, result);
+            }
+            Request::Sleep(sender) => {
+                let result = with_reconnect(lora_e5, reconnect, Yes, |lora_e5| lora_e5.sleep());
+                R::send(sender, result);
+            }
+            Request::Wake(sender) => {
+                let result = with_reconnect(lora_e5, reconnect, Yes, |lora_e5| lora_e5.wake());
+                R::send(sender, result);
+            }
+            Request::Reset(soft, sender) => {
+                let result = with_reconnect(lora_e5, reconnect, No, |lora_e5| {
+                    if soft {
+                        lora_e5.soft_reset()
+                    } else {
+                        lora_e5.reset()
+                    }
+                });
+                R::send(sender, result);
+            }
+            Request::FactoryReset(sender) => {
+                let result =
+                    with_reconnect(lora_e5, reconnect, No, |lora_e5| lora_e5.factory_reset());
+                R::send(sender, result);
+            }
+            Request::SendData(data, port, confirmed, timeout, sender) => {
+                let result = with_reconnect(lora_e5, reconnect, No, |lora_e5| match timeout {
+                    Some(timeout) => lora_e5.send_with_timeout(&data, port, confirmed, timeout),
+                    None => lora_e5.send(&data, port, confirmed),
+                });
+                R::send(sender, result);
+            }
+            Request::SendAscii(data, port, confirmed, timeout, sender) => {
+                let result = with_reconnect(lora_e5, reconnect, No, |lora_e5| match timeout {
+                    Some(timeout) => {
+                        lora_e5.send_ascii_with_timeout(&data, port, confirmed, timeout)
+                    }
+                    None => lora_e5.send_ascii(&data, port, confirmed),
+                });
+                R::send(sender, result);
+            }
+            Request::PollDownlink(timeout, sender) => {
+                let result = with_reconnect(lora_e5, reconnect, Yes, |lora_e5| {
+                    lora_e5.poll_downlink(timeout)
+                });
+                R::send(sender, result);
+            }
+            Request::LinkCheck(port, sender) => {
+                let result =
+                    with_reconnect(lora_e5, reconnect, No, |lora_e5| lora_e5.link_check(port));
+                R::send(sender, result);
+            }
+            Request::Script(commands, sender) => {
+                let result = with_reconnect(lora_e5, reconnect, No, |lora_e5| {
+                    let commands: Vec<(&str, &str)> = commands
+                        .iter()
+                        .map(|(cmd, prelude)| (cmd.as_str(), prelude.as_str()))
+                        .collect();
+                    lora_e5.run_script(&commands)
+                });
+                R::send(sender, result);
+            }
+            Request::Shutdown => (),
+        }
+    }
+}
+
+/// How a worker tries to reopen a port that has gone away, such as when a USB serial adapter
+/// re-enumerates
+#[derive(Debug, Clone, Copy)]
+pub struct ReconnectPolicy {
+    /// Reconnection attempts before the request fails
+    pub attempts: u32,
+    /// Wait before the first attempt, doubling after each failed one
+    pub backoff: Duration,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            attempts: 5,
+            backoff: Duration::from_millis(500),
+        }
+    }
+}
+
+/// Whether a request can safely be run again once the port has been reopened. Anything that
+/// transmits or restarts the modem can't, since an I/O error doesn't tell whether the modem acted
+/// on the command before the port went away: replaying an uplink could send it twice and use up a
+/// frame counter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Replay {
+    Yes,
+    No,
+}
+
+/// Runs `f` against the modem. If it fails with an I/O error and a reconnect policy is set, the
+/// port is reopened and, if `replay` allows, `f` is run once more; otherwise the original error
+/// is returned once the port is back. If the port can't be reopened, the original error is
+/// returned.
+pub(crate) fn with_reconnect<const N: usize, T: Transport, R>(
+    lora_e5: &mut LoraE5<N, T>,
+    reconnect: Option<ReconnectPolicy>,
+    replay: Replay,
+    mut f: impl FnMut(&mut LoraE5<N, T>) -> crate::Result<R>,
+) -> crate::Result<R> {
+    let result = f(lora_e5);
+    match (result, reconnect) {
+        (Err(e), Some(policy)) if is_disconnect(&e) => {
+            if reconnect_with(lora_e5, policy) && replay == Replay::Yes {
+                f(lora_e5)
+            } else {
+                Err(e)
+            }
+        }
+        (result, _) => result,
+    }
+}
+
+fn is_disconnect(e: &Error) -> bool {
+    match e {
+        Error::Io(_) => true,
+        #[cfg(feature = "serialport")]
+        Error::SerialPort(_) => true,
+        _ => false,
+    }
+}
+
+fn reconnect_with<const N: usize, T: Transport>(
+    lora_e5: &mut LoraE5<N, T>,
+    policy: ReconnectPolicy,
+) -> bool {
+    let mut backoff = policy.backoff;
+    for _ in 0..policy.attempts {
+        std::thread::sleep(backoff);
+        match lora_e5.reconnect() {
+            Ok(()) => return true,
+            Err(Error::ReconnectUnsupported) => return false,
+            Err(_) => backoff *= 2,
+        }
+    }
+    false
+}
@@ -0,0 +1,53 @@
+//! Receive window timing and RX2 parameters.
+//!
+//! The first receive window's data rate follows the uplink's, lowered by the RX1 data rate offset
+//! the network sends in the join-accept or an RXParamSetupReq. The modem has no command to change
+//! that offset (`AT+RXWIN1` configures channel frequencies instead), so it can only be set from
+//! the network server.
+use super::*;
+
+impl<const N: usize, T: Transport> LoraE5<N, T> {
+    /// Sets the delay between the end of an uplink and the opening of the first receive window
+    pub fn set_rx1_delay(&mut self, ms: u16) -> Result {
+        const EXPECTED_PRELUDE: &str = "+DELAY: ";
+        let cmd = format!("AT+DELAY=RX1,{ms}");
+        self.write_command(&cmd)?;
+        let n = self.read_until_break(self.command_timeout)?;
+        self.check_framed_response(n, EXPECTED_PRELUDE, &format!("RX1, {ms}"))
+    }
+
+    pub fn get_rx1_delay(&mut self) -> Result<u16> {
+        const EXPECTED_PRELUDE: &str = "+DELAY: RX1, ";
+        let n = self.query("AT+DELAY=RX1")?;
+        let response = self.framed_response(n, EXPECTED_PRELUDE)?;
+        Ok(response.trim_end().parse().map_err(ParseError::from)?)
+    }
+
+    /// Sets the frequency and data rate of the second receive window
+    pub fn set_rx2(&mut self, freq_hz: u32, dr: DR) -> Result {
+        const EXPECTED_PRELUDE: &str = "+RXWIN2: ";
+        let cmd = format!("AT+RXWIN2={},DR{}", format_mhz(freq_hz), dr.as_str());
+        self.write_command(&cmd)?;
+        let n = self.read_until_break(self.command_timeout)?;
+        let expected = format!("{freq_hz}, DR{}", dr.as_str());
+        self.check_framed_response(n, EXPECTED_PRELUDE, &expected)
+    }
+
+    /// Reads the frequency (in Hz) and data rate of the second receive window
+    pub fn get_rx2(&mut self) -> Result<(u32, DR)> {
+        const EXPECTED_PRELUDE: &str = "+RXWIN2: ";
+        let n = self.query("AT+RXWIN2")?;
+        let response = self.framed_response(n, EXPECTED_PRELUDE)?;
+        match response.trim_end().split_once(", ") {
+            Some((freq_hz, dr)) => Ok((
+                freq_hz.parse().map_err(ParseError::from)?,
+                DR::from_str(dr)?,
+            )),
+            None => Err(Error::unexpected_framed(
+                EXPECTED_PRELUDE,
+                "<frequency>, <data rate>",
+                response,
+            )),
+        }
+    }
+}
@@ -0,0 +1,42 @@
+use super::*;
+
+/// A snapshot of the modem's configuration and session, as read by `LoraE5::status`. A field is
+/// `None` if its query failed, such as DevAddr on a modem that hasn't been assigned one.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModemStatus {
+    pub version: Option<Version>,
+    pub region: Option<Region>,
+    pub mode: Option<Mode>,
+    pub class: Option<Class>,
+    pub datarate: Option<DR>,
+    pub adr: Option<bool>,
+    /// Whether a join succeeded through this handle, as the modem can't be asked
+    pub joined_this_session: bool,
+    pub dev_eui: Option<DevEui>,
+    pub app_eui: Option<AppEui>,
+    pub dev_addr: Option<DevAddr>,
+}
+
+impl<const N: usize, T: Transport> LoraE5<N, T> {
+    /// Reads everything in `ModemStatus`, one query at a time. Failed queries leave their field
+    /// empty rather than failing the whole call, so this suits diagnostics. Only a modem that
+    /// doesn't answer the first query fails the call, rather than every query timing out in turn.
+    pub fn status(&mut self) -> Result<ModemStatus> {
+        let version = match self.get_version_parsed() {
+            Err(e @ (Error::PartialResponse(_) | Error::Io(_))) => return Err(e),
+            version => version.ok(),
+        };
+        Ok(ModemStatus {
+            version,
+            region: self.get_region().ok(),
+            mode: self.get_mode().ok(),
+            class: self.get_class().ok(),
+            datarate: self.get_datarate().ok(),
+            adr: self.get_adr().ok(),
+            joined_this_session: self.joined_this_session(),
+            dev_eui: self.get_dev_eui().ok(),
+            app_eui: self.get_app_eui().ok(),
+            dev_addr: self.get_dev_addr().ok(),
+        })
+    }
+}
@@ -0,0 +1,262 @@
+//! A worker thread that serializes access to the modem without an async runtime. It serves the
+//! same requests as the tokio-based `process` module, but they are sent over `std::sync::mpsc`
+//! channels and answered through a blocking `Client`.
+use crate::request::{configuration, Reply};
+use crate::{ActivationCredentials, AppEui, Class, DevAddr, DevEui, ProvisionConfig, Region, DR};
+use crate::{
+    DeviceStatus, Downlink, Error as LoraE5Error, JoinResponse, LinkCheck, LoraE5, ModemStatus,
+    SendReport, Transport,
+};
+use std::{
+    fmt,
+    sync::mpsc,
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+pub type Result<T = ()> = std::result::Result<T, Error>;
+
+/// Answers the worker's requests over `std::sync::mpsc` channels
+#[derive(Debug)]
+pub struct Mpsc;
+
+impl Reply for Mpsc {
+    type Sender<T: fmt::Debug + Send + 'static> = mpsc::Sender<Result<T>>;
+
+    fn send<T: fmt::Debug + Send + 'static>(sender: Self::Sender<T>, result: crate::Result<T>) {
+        let _ = sender.send(result.map_err(|e| e.into()));
+    }
+}
+
+pub type Request = crate::request::Request<Mpsc>;
+
+#[derive(Clone)]
+pub struct Client {
+    sender: mpsc::Sender<Request>,
+}
+
+impl Client {
+    fn request<T>(&self, request: impl FnOnce(mpsc::Sender<Result<T>>) -> Request) -> Result<T> {
+        let (tx, rx) = mpsc::channel();
+        self.sender.send(request(tx))?;
+        rx.recv()?
+    }
+
+    pub fn at_command(&self, cmd: &str, timeout: Duration) -> Result<String> {
+        self.request(|tx| Request::At(cmd.to_string(), timeout, tx))
+    }
+
+    /// Sends a raw AT command and returns every line of the response, which is considered
+    /// complete once the modem has been quiet for `quiet`
+    pub fn at_command_multiline(&self, cmd: &str, quiet: Duration) -> Result<String> {
+        self.request(|tx| Request::AtMultiline(cmd.to_string(), quiet, tx))
+    }
+
+    pub fn join(&self, force: bool) -> Result<JoinResponse> {
+        self.request(|tx| Request::Join(force, None, tx))
+    }
+
+    /// Joins, waiting up to `timeout` for the join to finish rather than the default 20 seconds
+    pub fn join_with_timeout(&self, force: bool, timeout: Duration) -> Result<JoinResponse> {
+        self.request(|tx| Request::Join(force, Some(timeout), tx))
+    }
+
+    /// Joins, trying again after `backoff` when the join fails, up to `attempts` tries in total
+    pub fn join_with_retries(
+        &self,
+        force: bool,
+        attempts: u8,
+        backoff: Duration,
+    ) -> Result<JoinResponse> {
+        self.request(|tx| Request::JoinWithRetries(force, attempts, backoff, tx))
+    }
+
+    pub fn region(&self, region: Region) -> Result {
+        self.request(|tx| Request::Region(region, tx))
+    }
+
+    /// Restricts a US915/AU915 modem to the eight channels of `subband` (1-8)
+    pub fn subband(&self, subband: u8) -> Result {
+        self.request(|tx| Request::Subband(subband, tx))
+    }
+
+    pub fn data_rate(&self, dr: DR) -> Result {
+        self.request(|tx| Request::DataRate(dr, tx))
+    }
+
+    /// Reads the current data rate, which ADR may have changed
+    pub fn get_data_rate(&self) -> Result<DR> {
+        self.request(Request::GetDataRate)
+    }
+
+    pub fn tx_power(&self, dbm: u8) -> Result<u8> {
+        self.request(|tx| Request::TxPower(dbm, tx))
+    }
+
+    pub fn get_tx_power(&self) -> Result<u8> {
+        self.request(Request::GetTxPower)
+    }
+
+    pub fn get_vdd(&self) -> Result<f32> {
+        self.request(Request::GetVdd)
+    }
+
+    /// Reads the modem's internal temperature in Celsius
+    pub fn get_temperature(&self) -> Result<f32> {
+        self.request(Request::GetTemperature)
+    }
+
+    /// Reads the battery level and margin the modem reports to a DevStatusReq
+    pub fn get_device_status(&self) -> Result<DeviceStatus> {
+        self.request(Request::GetDeviceStatus)
+    }
+
+    pub fn class(&self, class: Class) -> Result {
+        self.request(|tx| Request::Class(class, tx))
+    }
+
+    pub fn get_class(&self) -> Result<Class> {
+        self.request(Request::GetClass)
+    }
+
+    /// Reads a snapshot of the modem's configuration and session, as `LoraE5::status`
+    pub fn status(&self) -> Result<ModemStatus> {
+        self.request(Request::Status)
+    }
+
+    pub fn adr(&self, enable: bool) -> Result {
+        self.request(|tx| Request::Adr(enable, tx))
+    }
+
+    pub fn get_adr(&self) -> Result<bool> {
+        self.request(Request::GetAdr)
+    }
+
+    /// Sets the activation mode and credentials for `region`, restricting a US915/AU915 modem to
+    /// `subband` if given
+    pub fn configure(
+        &self,
+        credentials: impl Into<ActivationCredentials>,
+        region: Region,
+        subband: Option<u8>,
+    ) -> Result {
+        let config = configuration(credentials, region, subband);
+        self.request(|tx| Request::Configure(config, tx))
+    }
+
+    /// Configures the modem and joins, as `LoraE5::provision`
+    pub fn provision(&self, config: ProvisionConfig) -> Result<JoinResponse> {
+        self.request(|tx| Request::Provision(config, tx))
+    }
+
+    pub fn get_app_eui(&self) -> Result<AppEui> {
+        self.request(Request::GetAppEui)
+    }
+
+    pub fn get_dev_eui(&self) -> Result<DevEui> {
+        self.request(Request::GetDevEui)
+    }
+
+    pub fn get_dev_addr(&self) -> Result<DevAddr> {
+        self.request(Request::GetDevAddr)
+    }
+
+    pub fn send(&self, data: Vec<u8>, port: u8, confirmed: bool) -> Result<SendReport> {
+        self.request(|tx| Request::SendData(data, port, confirmed, None, tx))
+    }
+
+    pub fn send_with_timeout(
+        &self,
+        data: Vec<u8>,
+        port: u8,
+        confirmed: bool,
+        timeout: Duration,
+    ) -> Result<SendReport> {
+        self.request(|tx| Request::SendData(data, port, confirmed, Some(timeout), tx))
+    }
+
+    pub fn send_ascii(&self, data: String, port: u8, confirmed: bool) -> Result<SendReport> {
+        self.request(|tx| Request::SendAscii(data, port, confirmed, None, tx))
+    }
+
+    pub fn send_ascii_with_timeout(
+        &self,
+        data: String,
+        port: u8,
+        confirmed: bool,
+        timeout: Duration,
+    ) -> Result<SendReport> {
+        self.request(|tx| Request::SendAscii(data, port, confirmed, Some(timeout), tx))
+    }
+
+    /// Waits up to `timeout` for an unsolicited downlink (eg: Class C)
+    pub fn poll_downlink(&self, timeout: Duration) -> Result<Option<Downlink>> {
+        self.request(|tx| Request::PollDownlink(timeout, tx))
+    }
+
+    /// Puts the modem into low power mode. Other requests will fail until `wake` is called.
+    pub fn sleep(&self) -> Result {
+        self.request(Request::Sleep)
+    }
+
+    pub fn wake(&self) -> Result {
+        self.request(Request::Wake)
+    }
+
+    /// Restarts the modem, using `AT+RESET` if `soft` is set and `ATZ` otherwise
+    pub fn reset(&self, soft: bool) -> Result {
+        self.request(|tx| Request::Reset(soft, tx))
+    }
+
+    /// Restores factory defaults. Credentials and region must be configured again afterwards.
+    pub fn factory_reset(&self) -> Result {
+        self.request(Request::FactoryReset)
+    }
+
+    /// Runs a link check, sending an empty uplink on `port` to carry the request
+    pub fn link_check(&self, port: u8) -> Result<LinkCheck> {
+        self.request(|tx| Request::LinkCheck(port, tx))
+    }
+
+    /// Runs `(command, expected_prelude)` pairs in order, stopping at the first failure; see
+    /// `LoraE5::run_script`
+    pub fn run_script(&self, commands: Vec<(String, String)>) -> Result<Vec<String>> {
+        self.request(|tx| Request::Script(commands, tx))
+    }
+
+    pub fn send_shutdown(&self) -> Result {
+        Ok(self.sender.send(Request::Shutdown)?)
+    }
+}
+
+/// Moves the modem onto a worker thread, returning a client for it and the thread's handle. The
+/// worker exits once it receives a shutdown request or every client has been dropped.
+pub fn spawn<const N: usize, T>(lora_e5: LoraE5<N, T>) -> (Client, JoinHandle<()>)
+where
+    T: Transport + Send + 'static,
+{
+    let (sender, receiver) = mpsc::channel();
+    let handle = thread::spawn(move || run(lora_e5, receiver));
+    (Client { sender }, handle)
+}
+
+fn run<const N: usize, T: Transport>(mut lora_e5: LoraE5<N, T>, receiver: mpsc::Receiver<Request>) {
+    while let Ok(request) = receiver.recv() {
+        if let Request::Shutdown = request {
+            return;
+        }
+        request.handle(&mut lora_e5, None);
+    }
+}
+
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("lora e5: {0}")]
+    LoraE5(#[from] LoraE5Error),
+    #[error("request send error: {0}")]
+    RequestSendError(#[from] mpsc::SendError<Request>),
+    #[error("response receive error: {0}")]
+    ResponseReceiveError(#[from] mpsc::RecvError),
+}
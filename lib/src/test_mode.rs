@@ -0,0 +1,211 @@
+use super::*;
+
+/// A packet received in test (P2P) mode
+#[derive(Debug, Clone, PartialEq)]
+pub struct TestPacket {
+    pub payload: Vec<u8>,
+    pub rssi: isize,
+    pub snr: f32,
+}
+
+/// Radio parameters for test (P2P) mode, as set by `test_rfcfg`. The modem always uses a 4/5
+/// coding rate, so it can't be configured.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RadioConfig {
+    pub freq_hz: u32,
+    /// Spreading factor, 7-12
+    pub sf: u8,
+    /// Bandwidth in kHz: 125, 250 or 500
+    pub bw_khz: u16,
+    /// Preamble length in symbols sent before each packet
+    pub tx_preamble: u16,
+    /// Preamble length in symbols expected before each received packet
+    pub rx_preamble: u16,
+    /// Transmit power in dBm
+    pub power: u8,
+    pub crc: bool,
+    pub iq_inverted: bool,
+    /// Use the public LoRaWAN sync word rather than the private one
+    pub public_network: bool,
+}
+
+impl RadioConfig {
+    /// A configuration with 8 symbol preambles, 14 dBm, CRC on, IQ not inverted and the private
+    /// sync word, which suits a link between two modems
+    pub fn new(freq_hz: u32, sf: u8, bw_khz: u16) -> Self {
+        Self {
+            freq_hz,
+            sf,
+            bw_khz,
+            tx_preamble: 8,
+            rx_preamble: 8,
+            power: 14,
+            crc: true,
+            iq_inverted: false,
+            public_network: false,
+        }
+    }
+
+    fn validate(&self) -> Result {
+        if (7..=12).contains(&self.sf) && [125, 250, 500].contains(&self.bw_khz) {
+            Ok(())
+        } else {
+            Err(Error::InvalidRadioConfig {
+                sf: self.sf,
+                bw_khz: self.bw_khz,
+            })
+        }
+    }
+}
+
+impl<const N: usize, T: Transport> LoraE5<N, T> {
+    /// Configures the radio for test (P2P) mode with `AT+TEST=RFCFG`. Both ends of a link must
+    /// use the same frequency, spreading factor and bandwidth. The modem must already be in
+    /// `Mode::Test`.
+    pub fn test_config(&mut self, freq_hz: u32, sf: u8, bw_khz: u16, power: u8) -> Result {
+        let mut cfg = RadioConfig::new(freq_hz, sf, bw_khz);
+        cfg.power = power;
+        self.test_rfcfg(&cfg)
+    }
+
+    /// Applies every radio parameter of `cfg` with `AT+TEST=RFCFG` and checks the modem's echo
+    /// of them. The modem must already be in `Mode::Test`.
+    pub fn test_rfcfg(&mut self, cfg: &RadioConfig) -> Result {
+        const EXPECTED_PRELUDE: &str = "+TEST: RFCFG ";
+        cfg.validate()?;
+        let on_off = |on| if on { "ON" } else { "OFF" };
+        let cmd = format!(
+            "AT+TEST=RFCFG,{},SF{},{},{},{},{},{},{},{}",
+            format_mhz(cfg.freq_hz),
+            cfg.sf,
+            cfg.bw_khz,
+            cfg.tx_preamble,
+            cfg.rx_preamble,
+            cfg.power,
+            on_off(cfg.crc),
+            on_off(cfg.iq_inverted),
+            on_off(cfg.public_network),
+        );
+        self.write_command(&cmd)?;
+        let n = self.read_until_break(self.command_timeout)?;
+        let response = self.framed_response(n, EXPECTED_PRELUDE)?;
+        let expected = [
+            format!("F:{}", cfg.freq_hz),
+            format!("SF{}", cfg.sf),
+            format!("BW{}K", cfg.bw_khz),
+            format!("TXPR:{}", cfg.tx_preamble),
+            format!("RXPR:{}", cfg.rx_preamble),
+            format!("POW:{}dBm", cfg.power),
+            format!("CRC:{}", on_off(cfg.crc)),
+            format!("IQ:{}", on_off(cfg.iq_inverted)),
+            format!("NET:{}", on_off(cfg.public_network)),
+        ];
+        let echoed: Vec<&str> = response.trim_end().split(',').map(str::trim).collect();
+        // the modem may lower the power to what the region allows, so it isn't compared
+        let matches = echoed.len() == expected.len()
+            && echoed
+                .iter()
+                .zip(&expected)
+                .all(|(echoed, expected)| echoed == expected || echoed.starts_with("POW:"));
+        if matches {
+            Ok(())
+        } else {
+            Err(Error::unexpected_framed(
+                EXPECTED_PRELUDE,
+                expected.join(", "),
+                response,
+            ))
+        }
+    }
+
+    pub fn test_tx(&mut self, data: &[u8]) -> Result {
+        const TX_DONE: &str = "+TEST: TX DONE\r\n";
+        let cmd = format!("AT+TEST=TXLRPKT,\"{}\"", hex::encode(data));
+        self.write_command(&cmd)?;
+        let n = self.read_until_pattern(&[TX_DONE], self.command_timeout)?;
+        let response = std::str::from_utf8(&self.buf[..n])?;
+        framed_response(response, "+TEST: TXLRPKT ")?;
+        Ok(())
+    }
+
+    /// Starts an unmodulated carrier on `freq_hz` at `power` dBm with `AT+TEST=TXCW`, for RF
+    /// certification testing. This replaces the radio configuration from `test_rfcfg`. The modem
+    /// keeps transmitting until `test_stop` is called, so a carrier left running will occupy the
+    /// channel indefinitely.
+    pub fn test_tx_cw(&mut self, freq_hz: u32, power: u8) -> Result {
+        const EXPECTED_PRELUDE: &str = "+TEST: ";
+        let mut cfg = RadioConfig::new(freq_hz, 7, 125);
+        cfg.power = power;
+        self.test_rfcfg(&cfg)?;
+        self.write_command("AT+TEST=TXCW")?;
+        let n = self.read_until_break(self.command_timeout)?;
+        self.check_framed_response(n, EXPECTED_PRELUDE, "TXCW")
+    }
+
+    /// Stops whatever the radio is doing in test mode, such as a carrier from `test_tx_cw` or a
+    /// receive from `test_rx`, with `AT+TEST=STOP`
+    pub fn test_stop(&mut self) -> Result {
+        const EXPECTED_PRELUDE: &str = "+TEST: ";
+        self.write_command("AT+TEST=STOP")?;
+        let n = self.read_until_break(self.command_timeout)?;
+        self.check_framed_response(n, EXPECTED_PRELUDE, "STOP")
+    }
+
+    /// Puts the radio in receive and waits up to `timeout` for a packet
+    pub fn test_rx(&mut self, timeout: Duration) -> Result<Option<TestPacket>> {
+        const EXPECTED_PRELUDE: &str = "+TEST: ";
+        self.write_command("AT+TEST=RXLRPKT")?;
+        let n = self.read_until_break(self.command_timeout)?;
+        self.check_framed_response(n, EXPECTED_PRELUDE, "RXLRPKT")?;
+
+        let deadline = time::Instant::now() + timeout;
+        let mut response = String::new();
+        loop {
+            let remaining = deadline.saturating_duration_since(time::Instant::now());
+            if remaining.is_zero() {
+                return Ok(None);
+            }
+            match self.read_until_break(remaining) {
+                Ok(n) => {
+                    let line = std::str::from_utf8(&self.buf[..n])?;
+                    response.push_str(line);
+                    if line.starts_with("+TEST: RX ") {
+                        return parse_test_packet(&response).map(Some);
+                    }
+                }
+                Err(Error::PartialResponse(_)) => return Ok(None),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+/// Parses the two lines printed for a received packet:
+///   +TEST: LEN:2, RSSI:-41, SNR:10
+///   +TEST: RX "AABB"
+pub(crate) fn parse_test_packet(response: &str) -> Result<TestPacket> {
+    let failed = || Error::FailedToParseTestPacket(response.to_string());
+    let mut rssi = None;
+    let mut snr = None;
+    let mut payload = None;
+    for line in response.lines() {
+        let line = line.trim_end();
+        if let Some(signal) = line.strip_prefix("+TEST: LEN:") {
+            for field in signal.split(", ") {
+                if let Some(value) = field.strip_prefix("RSSI:") {
+                    rssi = Some(value.parse().map_err(Error::FailedToParseRssiInt)?);
+                } else if let Some(value) = field.strip_prefix("SNR:") {
+                    snr = Some(value.parse().map_err(Error::FailedToParseSnrF32)?);
+                }
+            }
+        } else if let Some(rx) = line.strip_prefix("+TEST: RX ") {
+            let hex = rx.trim_matches('"');
+            payload = Some(hex::decode(hex).map_err(ParseError::from)?);
+        }
+    }
+    Ok(TestPacket {
+        payload: payload.ok_or_else(failed)?,
+        rssi: rssi.ok_or_else(failed)?,
+        snr: snr.ok_or_else(failed)?,
+    })
+}
@@ -1,4 +1,4 @@
-/// These tests all require a physical LoRa E5.
+/// Most of these tests require a physical LoRa E5.
 /// Run them one at a time to avoid port collisions:
 ///   ie: cargo test --  --nocapture --test-threads 1
 use super::*;
@@ -8,6 +8,22 @@ fn lora_test_hardware() -> LoraE5<256_usize> {
     LoraE5::<256>::open_usb(SILICON_LABS_VID, CP210X_UART_BRIDGE_PID).unwrap()
 }
 
+/// A LoraE5 attached to one end of a pseudo-terminal, with the other end returned so tests can
+/// play the part of the modem.
+#[cfg(unix)]
+fn lora_pty<const N: usize>() -> (LoraE5<N>, serialport::TTYPort) {
+    use serialport::SerialPort;
+    let (modem, mut port) = serialport::TTYPort::pair().unwrap();
+    port.set_timeout(Duration::from_millis(10)).unwrap();
+    (LoraE5::new(Box::new(port), DEFAULT_TIMEOUT), modem)
+}
+
+/// A LoraE5 over a scripted `MockPort`, with a handle to the script.
+fn lora_mock<const N: usize>() -> (LoraE5<N, MockPort>, MockPort) {
+    let mock = MockPort::new();
+    (LoraE5::new(mock.clone(), DEFAULT_TIMEOUT), mock)
+}
+
 #[test]
 fn usb_open() {
     let _lora_e5 = lora_test_hardware();
@@ -25,6 +41,22 @@ fn get_version() {
     let _version = lora_e5.get_version().unwrap();
 }
 
+#[test]
+fn parse_version() {
+    let (mut lora_e5, mock) = lora_mock::<64>();
+    mock.expect("AT+VER", "+VER: 4.0.11\r\n");
+    let version = lora_e5.get_version_parsed().unwrap();
+    assert_eq!(version, Version::new(4, 0, 11));
+    assert!(version >= Version::new(1, 0, 1));
+    assert!(version < Version::new(4, 1, 0));
+
+    let version = Version::from_str("1.0.0-beta").unwrap();
+    assert_eq!(version.variant.as_deref(), Some("beta"));
+    assert_eq!(version.to_string(), "1.0.0-beta");
+    assert!(Version::from_str("1.0").is_err());
+    assert!(Version::from_str("1.0.0.0").is_err());
+}
+
 #[test]
 fn set_subband() {
     let mut lora_e5 = lora_test_hardware();
@@ -126,18 +158,2066 @@ fn join_and_send() {
 
 #[test]
 fn parse_signal() {
+    let (mut lora_e5, mock) = lora_mock::<256>();
+    mock.expect("AT+PORT=3", "+PORT: 3\r\n");
+    mock.expect(
+        "AT+CMSGHEX=\"01020304\"",
+        "+CMSGHEX: Start\r
++CMSGHEX: Wait ACK\r
++CMSGHEX: FPENDING\r
++CMSGHEX: ACK Received\r
++CMSGHEX: RXWIN1, RSSI -79, SNR 7.0\r
++CMSGHEX: Done\r
+",
+    );
+    let report = lora_e5.send(&[1, 2, 3, 4], 3, true).unwrap();
+    assert_eq!(
+        report.transcript.events,
+        vec![
+            SendEvent::Start,
+            SendEvent::WaitAck,
+            SendEvent::FramePending,
+            SendEvent::AckReceived,
+            SendEvent::RxWindow {
+                window: 1,
+                rssi: -79,
+                snr: 7.0
+            },
+            SendEvent::Done,
+        ]
+    );
+    assert!(report.transcript.frame_pending());
+    let SendOutcome::Acked(Some(downlink)) = report.outcome else {
+        panic!("uplink was not acknowledged with a downlink")
+    };
+    assert_eq!(downlink.rssi, -79);
+    assert_eq!(downlink.snr, 7.0);
+    assert!(downlink.ack);
+
+    mock.expect("AT+LW=BAT", "+LW: BAT, 254\r\n");
+    assert_eq!(
+        lora_e5.get_device_status().unwrap(),
+        DeviceStatus {
+            battery: 254,
+            margin: Some(7)
+        }
+    );
+    assert!(mock.is_done());
+}
+
+#[test]
+fn framed_responses() {
+    let (mut lora_e5, mock) = lora_mock::<64>();
+    mock.expect("AT+ID=DevEui", "+ID: DevEui, 60:81:F9:A7:75:27:85:64\r\n");
+    assert_eq!(
+        lora_e5.get_dev_eui().unwrap(),
+        DevEui::from_str("6081F9A775278564").unwrap()
+    );
+    mock.expect("AT+MODE=LWOTAA", "+MODE: LWOTAA\r\n");
+    lora_e5.set_mode(Mode::Otaa).unwrap();
+    mock.expect("AT+ID=DevEui, 0000000000000001", "+ID: ERROR(-1)\r\n");
+    let error = lora_e5
+        .set_dev_eui(&DevEui::from_str("0000000000000001").unwrap())
+        .unwrap_err();
+    assert!(matches!(error, Error::CommandError { code: -1 }));
+    assert_eq!(
+        error.to_string(),
+        "modem replied ERROR(-1): invalid parameter"
+    );
+    mock.expect("AT+MODE=LWABP", "+MODE: LWOTAA\r\n");
+    assert!(matches!(
+        lora_e5.set_mode(Mode::Abp),
+        Err(Error::UnexpectedResponse { .. })
+    ));
+    mock.expect("AT+PORT=5", "+AT: OK\r\n");
+    assert!(matches!(
+        lora_e5.set_port(5),
+        Err(Error::UnexpectedResponse { .. })
+    ));
+    assert!(mock.is_done());
+}
+
+#[test]
+fn join_responses() {
+    let (mut lora_e5, mock) = lora_mock::<256>();
+    mock.expect(
+        "AT+JOIN",
+        "+JOIN: Start\r
++JOIN: NORMAL\r
++JOIN: Network joined\r
++JOIN: NetID 000024 DevAddr 48:00:00:01\r
++JOIN: Done\r
+",
+    );
+    assert!(!lora_e5.joined_this_session());
+    let JoinResponse::JoinComplete(join_accept) = lora_e5.join().unwrap() else {
+        panic!("join did not complete")
+    };
+    assert_eq!(
+        join_accept.dev_addr,
+        Some(DevAddr::from_str("48000001").unwrap())
+    );
+    assert!(lora_e5.joined_this_session());
+    mock.expect("AT+ID=DevAddr", "+ID: DevAddr, 48:00:00:01\r\n");
+    assert_eq!(
+        lora_e5.get_dev_addr().unwrap(),
+        DevAddr::from_str("48000001").unwrap()
+    );
+    mock.expect("AT+JOIN", "+JOIN: Joined already\r\n");
+    assert_eq!(lora_e5.join().unwrap(), JoinResponse::AlreadyJoined);
+    mock.expect(
+        "AT+JOIN=FORCE",
+        "+JOIN: Start\r\n+JOIN: Join failed\r\n+JOIN: Done\r\n",
+    );
+    assert_eq!(
+        lora_e5.force_join().unwrap(),
+        JoinResponse::JoinFailed(JoinFailure::NoAccept)
+    );
+    assert!(!lora_e5.joined_this_session());
+    assert!(mock.is_done());
+}
+
+#[test]
+fn region_round_trip() {
+    for region in [
+        Region::Eu868,
+        Region::Us915,
+        Region::Au915,
+        Region::As923,
+        Region::Kr920,
+        Region::In865,
+        Region::Ru864,
+    ] {
+        assert_eq!(Region::from_str(region.as_str()).unwrap(), region);
+    }
+    assert_eq!(Region::from_str("au915").unwrap(), Region::Au915);
+    assert!(Region::from_str("XX123").is_err());
+}
+
+#[test]
+fn datarate_termination_patterns() {
+    assert_eq!(
+        DR::_0.termination_pattern(Region::Us915).unwrap(),
+        "US915 DR0  SF10 BW125K \r\n"
+    );
+    assert_eq!(
+        DR::_1.termination_pattern(Region::Us915).unwrap(),
+        "US915 DR1  SF9  BW125K \r\n"
+    );
+    assert_eq!(
+        DR::_4.termination_pattern(Region::Us915).unwrap(),
+        "US915 DR4  SF8  BW500K \r\n"
+    );
+    assert_eq!(
+        DR::_5.termination_pattern(Region::Eu868).unwrap(),
+        "EU868 DR5  SF7  BW125K \r\n"
+    );
+    assert!(DR::_5.termination_pattern(Region::Us915).is_none());
+    assert_eq!(DR::all_patterns(Region::Us915).len(), 11);
+    assert_eq!(DR::from_str("DR12").unwrap(), DR::_12);
+    assert!(DR::from_str("16").is_err());
+}
+
+#[test]
+fn set_channel_response() {
+    use parse::check_framed_response;
+    let expected = channel_response(8, true);
+    check_framed_response("+CH: CH8 on\r\n", "+CH: CH", &expected).unwrap();
+    assert!(check_framed_response("+CH: CH8 off\r\n", "+CH: CH", &expected).is_err());
+
+    let expected = channel_response(0, false);
+    check_framed_response("+CH: CH0 off\r\n", "+CH: CH", &expected).unwrap();
+    assert!(check_framed_response("+CH: CH0 on\r\n", "+CH: CH", &expected).is_err());
+}
+
+#[cfg(unix)]
+#[test]
+fn read_into_full_buffer() {
+    use std::io::Write;
+    let (mut lora_e5, mut modem) = lora_pty::<16>();
+    modem
+        .write_all(b"+VER: this response is too long\r\n")
+        .unwrap();
+    match lora_e5.read_until_break(Duration::from_millis(500)) {
+        Err(Error::BufferFull(16)) => (),
+        other => panic!("expected BufferFull, got {other:?}"),
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn read_delayed_response() {
+    use std::io::Write;
+    let (mut lora_e5, mut modem) = lora_pty::<64>();
+    let writer = std::thread::spawn(move || {
+        std::thread::sleep(Duration::from_millis(200));
+        modem.write_all(b"+VER: 4.0.11\r\n").unwrap();
+        modem
+    });
+    let n = lora_e5.read_until_break(Duration::from_secs(1)).unwrap();
+    let version = lora_e5.framed_response(n, "+VER: ").unwrap();
+    assert_eq!(version.trim_end(), "4.0.11");
+    writer.join().unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn set_tx_power_clamped() {
+    use std::io::Write;
+    let (mut lora_e5, mut modem) = lora_pty::<64>();
+    modem.write_all(b"+POWER: 20\r\n").unwrap();
+    assert_eq!(lora_e5.set_tx_power(30).unwrap(), 20);
+}
+
+#[cfg(unix)]
+#[test]
+fn get_adr() {
+    use std::io::Write;
+    let (mut lora_e5, mut modem) = lora_pty::<64>();
+    modem.write_all(b"+ADR: OFF\r\n").unwrap();
+    assert!(!lora_e5.get_adr().unwrap());
+}
+
+#[test]
+fn parse_downlink_payload() {
+    let response = "+MSGHEX: Start\r
++MSGHEX: FPENDING\r
++MSGHEX: PORT: 2; RX: \"A1B2\"\r
++MSGHEX: RXWIN1, RSSI -54, SNR 9.5\r
++MSGHEX: Done\r
+";
+    let downlink = parse_downlink(response).unwrap().unwrap();
+    assert_eq!(downlink.rssi, -54);
+    assert_eq!(downlink.snr, 9.5);
+    assert_eq!(downlink.port, Some(2));
+    assert_eq!(downlink.payload, Some(vec![0xA1, 0xB2]));
+    assert!(downlink.frame_pending);
+
+    let response = "+MSGHEX: PORT: 7; RX \"00ff\"\r\n+MSGHEX: RXWIN2, RSSI -100, SNR -3.2\r\n";
+    let downlink = parse_downlink(response).unwrap().unwrap();
+    assert_eq!(downlink.port, Some(7));
+    assert_eq!(downlink.payload, Some(vec![0x00, 0xFF]));
+    assert!(!downlink.frame_pending);
+}
+
+#[test]
+fn parse_downlink_without_payload() {
+    let response = "+CMSGHEX: ACK Received\r\n+CMSGHEX: RXWIN1, RSSI -79, SNR 7.0\r\n";
+    let downlink = parse_downlink(response).unwrap().unwrap();
+    assert_eq!(downlink.rssi, -79);
+    assert_eq!(downlink.port, None);
+    assert_eq!(downlink.payload, None);
+}
+
+#[cfg(unix)]
+#[test]
+fn poll_class_c_downlink() {
+    use std::io::Write;
+    let (mut lora_e5, mut modem) = lora_pty::<128>();
+    assert!(lora_e5
+        .poll_downlink(Duration::from_millis(50))
+        .unwrap()
+        .is_none());
+    modem
+        .write_all(b"+MSG: PORT: 3; RX: \"0102\"\r\n+MSG: RXWIN0, RSSI -40, SNR 9.0\r\n")
+        .unwrap();
+    let downlink = lora_e5
+        .poll_downlink(Duration::from_millis(500))
+        .unwrap()
+        .unwrap();
+    assert_eq!(downlink.rssi, -40);
+    assert_eq!(downlink.port, Some(3));
+    assert_eq!(downlink.payload, Some(vec![1, 2]));
+}
+
+#[cfg(unix)]
+#[test]
+fn set_dev_addr() {
+    use std::io::Write;
+    let dev_addr = DevAddr::from_str("01:23:45:67").unwrap();
+    let (mut lora_e5, mut modem) = lora_pty::<64>();
+    modem.write_all(b"+ID: DevAddr, 01:23:45:67\r\n").unwrap();
+    lora_e5.set_dev_addr(&dev_addr).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn reset_skips_garbage() {
+    use std::io::Write;
+    let (mut lora_e5, mut modem) = lora_pty::<64>();
+    modem.write_all(b"\xff\x00\xfe+RESET: OK\r\n").unwrap();
+    lora_e5.soft_reset().unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn factory_reset_clears_region() {
+    use std::io::Write;
+    let (mut lora_e5, mut modem) = lora_pty::<64>();
+    modem.write_all(b"+DR: EU868\r\n").unwrap();
+    lora_e5.set_region(Region::Eu868).unwrap();
+    modem.write_all(b"+FDEFAULT: OK\r\n").unwrap();
+    lora_e5.factory_reset().unwrap();
+    assert_eq!(lora_e5.region, None);
+}
+
+#[cfg(unix)]
+#[test]
+fn set_uart_baud() {
+    use std::io::Write;
+    let (mut lora_e5, mut modem) = lora_pty::<64>();
+    modem.write_all(b"+UART: BR, 115200\r\n").unwrap();
+    lora_e5.set_uart_baud(115200).unwrap();
+    assert_eq!(lora_e5.port.baud_rate().unwrap(), 115200);
+}
+
+#[cfg(unix)]
+#[test]
+fn reconnect_keeps_uart_baud() {
+    use serialport::SerialPort;
+    use std::io::Write;
+    let (mut lora_e5, mut modem) = lora_pty::<64>();
+    let (_replacement_modem, mut replacement) = serialport::TTYPort::pair().unwrap();
+    replacement.set_baud_rate(DEFAULT_BAUD).unwrap();
+    let mut replacement = Some(replacement);
+    lora_e5.reopen = Some(Box::new(move || {
+        Ok(Box::new(replacement.take().unwrap()) as DefaultTransport)
+    }));
+    modem.write_all(b"+UART: BR, 115200\r\n").unwrap();
+    lora_e5.set_uart_baud(115200).unwrap();
+    lora_e5.reconnect().unwrap();
+    assert_eq!(lora_e5.port.baud_rate().unwrap(), 115200);
+}
+
+#[cfg(unix)]
+#[test]
+fn builder_command_timeout() {
+    use serialport::SerialPort;
+    let (_modem, port) = serialport::TTYPort::pair().unwrap();
+    let mut lora_e5 = LoraE5::<64>::builder()
+        .default_command_timeout(Duration::from_millis(50))
+        .open_path(port.name().unwrap())
+        .unwrap();
+    let start = time::Instant::now();
+    assert!(matches!(
+        lora_e5.get_version(),
+        Err(Error::PartialResponse(_))
+    ));
+    assert!(start.elapsed() < DEFAULT_TIMEOUT);
+}
+
+#[cfg(unix)]
+#[test]
+fn builder_framing() {
+    // a pseudo terminal keeps its control flags at 8N1, so only the input flags can be checked
+    use serialport::{DataBits, FlowControl, Parity, SerialPort, StopBits};
+    let (_modem, port) = serialport::TTYPort::pair().unwrap();
+    let lora_e5 = LoraE5::<64>::builder()
+        .framing(DataBits::Eight, Parity::None, StopBits::One)
+        .flow_control(FlowControl::Software)
+        .open_path(port.name().unwrap())
+        .unwrap();
+    assert_eq!(lora_e5.port.flow_control().unwrap(), FlowControl::Software);
+}
+
+#[cfg(unix)]
+#[test]
+fn builder_probes_on_open() {
+    use serialport::SerialPort;
+    let (_modem, port) = serialport::TTYPort::pair().unwrap();
+    assert!(matches!(
+        LoraE5::<64>::builder()
+            .probe(true)
+            .open_path(port.name().unwrap()),
+        Err(Error::Unresponsive { attempts: 3 })
+    ));
+}
+
+#[cfg(unix)]
+#[test]
+fn get_region_and_mode() {
+    use std::io::Write;
+    let (mut lora_e5, mut modem) = lora_pty::<64>();
+    modem.write_all(b"+DR: AU915\r\n").unwrap();
+    assert_eq!(lora_e5.get_region().unwrap(), Region::Au915);
+    modem.write_all(b"+MODE: LWOTAA\r\n").unwrap();
+    assert_eq!(lora_e5.get_mode().unwrap(), Mode::Otaa);
+    modem.write_all(b"+DR: CN470\r\n").unwrap();
+    assert!(matches!(
+        lora_e5.get_region(),
+        Err(Error::UnsupportedRegion(region)) if region == "CN470"
+    ));
+}
+
+#[test]
+fn parse_p2p_packet() {
+    let response = "+TEST: LEN:2, RSSI:-41, SNR:10\r\n+TEST: RX \"AABB\"\r\n";
+    let packet = test_mode::parse_test_packet(response).unwrap();
+    assert_eq!(packet.payload, vec![0xAA, 0xBB]);
+    assert_eq!(packet.rssi, -41);
+    assert_eq!(packet.snr, 10.0);
+    assert!(test_mode::parse_test_packet("+TEST: RX \"AABB\"\r\n").is_err());
+}
+
+#[cfg(unix)]
+#[test]
+fn p2p_config_and_transmit() {
+    use std::io::Write;
+    let (mut lora_e5, mut modem) = lora_pty::<256>();
+    modem
+        .write_all(b"+TEST: RFCFG F:868100000, SF7, BW125K, TXPR:8, RXPR:8, POW:14dBm, CRC:ON, IQ:OFF, NET:OFF\r\n")
+        .unwrap();
+    lora_e5.test_config(868_100_000, 7, 125, 14).unwrap();
+    modem
+        .write_all(b"+TEST: TXLRPKT \"0102\"\r\n+TEST: TX DONE\r\n")
+        .unwrap();
+    lora_e5.test_tx(&[1, 2]).unwrap();
+}
+
+#[test]
+fn radio_config() {
+    let (mut lora_e5, mock) = lora_mock::<256>();
+    let mut cfg = RadioConfig::new(868_100_000, 9, 250);
+    cfg.tx_preamble = 12;
+    cfg.power = 20;
+    cfg.iq_inverted = true;
+    mock.expect(
+        "AT+TEST=RFCFG,868.1,SF9,250,12,8,20,ON,ON,OFF",
+        "+TEST: RFCFG F:868100000, SF9, BW250K, TXPR:12, RXPR:8, POW:16dBm, CRC:ON, IQ:ON, NET:OFF\r\n",
+    );
+    lora_e5.test_rfcfg(&cfg).unwrap();
+
+    mock.expect(
+        "AT+TEST=RFCFG,868.1,SF9,250,12,8,20,ON,ON,OFF",
+        "+TEST: RFCFG F:868100000, SF9, BW250K, TXPR:12, RXPR:8, POW:16dBm, CRC:ON, IQ:OFF, NET:OFF\r\n",
+    );
+    assert!(matches!(
+        lora_e5.test_rfcfg(&cfg),
+        Err(Error::UnexpectedResponse { .. })
+    ));
+    assert!(mock.is_done());
+
+    for (sf, bw_khz) in [(6, 125), (13, 125), (7, 62)] {
+        assert!(matches!(
+            lora_e5.test_rfcfg(&RadioConfig::new(868_100_000, sf, bw_khz)),
+            Err(Error::InvalidRadioConfig { .. })
+        ));
+    }
+}
+
+#[test]
+fn continuous_wave() {
+    let (mut lora_e5, mock) = lora_mock::<256>();
+    mock.expect(
+        "AT+TEST=RFCFG,915,SF7,125,8,8,20,ON,OFF,OFF",
+        "+TEST: RFCFG F:915000000, SF7, BW125K, TXPR:8, RXPR:8, POW:20dBm, CRC:ON, IQ:OFF, NET:OFF\r\n",
+    );
+    mock.expect("AT+TEST=TXCW", "+TEST: TXCW\r\n");
+    mock.expect("AT+TEST=STOP", "+TEST: STOP\r\n");
+    lora_e5.test_tx_cw(915_000_000, 20).unwrap();
+    lora_e5.test_stop().unwrap();
+    assert!(mock.is_done());
+}
+
+#[test]
+fn send_empty() {
+    let (mut lora_e5, mock) = lora_mock::<256>();
+    mock.expect("AT+PORT=5", "+PORT: 5\r\n");
+    mock.expect(
+        "AT+MSGHEX",
+        "+MSGHEX: Start\r
++MSGHEX: FPENDING\r
++MSGHEX: PORT: 5; RX: \"0A0B\"\r
++MSGHEX: RXWIN1, RSSI -60, SNR 8.0\r
++MSGHEX: Done\r
+",
+    );
+    let downlink = lora_e5.send_empty(5, false).unwrap().unwrap();
+    assert_eq!(downlink.payload, Some(vec![0x0A, 0x0B]));
+    assert!(downlink.frame_pending);
+
+    mock.expect("AT+MSGHEX", "+MSGHEX: Start\r\n+MSGHEX: Done\r\n");
+    assert!(lora_e5.send_empty(5, false).unwrap().is_none());
+    assert!(mock.is_done());
+}
+
+#[test]
+fn parse_join_accept_details() {
+    let response = "+JOIN: Start\r
++JOIN: NORMAL\r
++JOIN: Network joined\r
++JOIN: NetID 000024 DevAddr 48:00:00:01\r
++JOIN: RXWIN1, RSSI -45, SNR 9.0\r
++JOIN: Done\r
+";
+    let join_accept = parse_join_accept(response).unwrap();
+    assert_eq!(join_accept.net_id.unwrap().to_string(), "000024");
+    assert_eq!(join_accept.dev_addr.unwrap().to_string(), "48000001");
+    assert_eq!(join_accept.rssi, Some(-45));
+    assert_eq!(join_accept.snr, Some(9.0));
+
+    let join_accept =
+        parse_join_accept("+JOIN: NetID 000013 DevAddr 260B1234\r\n+JOIN: Done\r\n").unwrap();
+    assert_eq!(
+        join_accept.dev_addr,
+        Some(DevAddr::from([0x26, 0x0B, 0x12, 0x34]))
+    );
+
+    let join_accept = parse_join_accept("+JOIN: Network joined\r\n+JOIN: Done\r\n").unwrap();
+    assert_eq!(join_accept, JoinAccept::default());
+}
+
+#[test]
+fn parse_link_check_answer() {
+    let response = "+MSGHEX: Start\r
++MSGHEX: Link 20, 2\r
++MSGHEX: RXWIN1, RSSI -60, SNR 8.5\r
++MSGHEX: Done\r
+";
+    let downlink = parse_downlink(response).unwrap().unwrap();
+    assert_eq!(
+        downlink.link_check,
+        Some(LinkCheck {
+            demod_margin: 20,
+            gateway_count: 2
+        })
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn get_vdd() {
+    use std::io::Write;
+    let (mut lora_e5, mut modem) = lora_pty::<64>();
+    modem.write_all(b"+VDD: 3.29V\r\n").unwrap();
+    assert_eq!(lora_e5.get_vdd().unwrap(), 3.29);
+    modem.write_all(b"+VDD: 3.30 V\r\n").unwrap();
+    assert_eq!(lora_e5.get_vdd().unwrap(), 3.30);
+}
+
+#[cfg(unix)]
+#[test]
+fn sleep_and_wake() {
+    use std::io::Write;
+    let (mut lora_e5, mut modem) = lora_pty::<64>();
+    // waking an awake modem is a no-op
+    lora_e5.wake().unwrap();
+    modem.write_all(b"+LOWPOWER: SLEEP\r\n").unwrap();
+    lora_e5.sleep().unwrap();
+    modem.write_all(b"+LOWPOWER: WAKEUP\r\n").unwrap();
+    lora_e5.wake().unwrap();
+    lora_e5.wake().unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn rx_windows() {
+    use std::io::Write;
+    let (mut lora_e5, mut modem) = lora_pty::<64>();
+    modem.write_all(b"+DELAY: RX1, 5000\r\n").unwrap();
+    lora_e5.set_rx1_delay(5000).unwrap();
+    modem.write_all(b"+DELAY: RX1, 1000\r\n").unwrap();
+    assert!(lora_e5.set_rx1_delay(5000).is_err());
+    modem.write_all(b"+RXWIN2: 923300000, DR8\r\n").unwrap();
+    lora_e5.set_rx2(923_300_000, DR::_8).unwrap();
+    modem.write_all(b"+RXWIN2: 869525000, DR0\r\n").unwrap();
+    assert_eq!(lora_e5.get_rx2().unwrap(), (869_525_000, DR::_0));
+}
+
+#[cfg(unix)]
+#[test]
+fn retries_extend_confirmed_send_timeout() {
+    use std::io::Write;
+    let (mut lora_e5, mut modem) = lora_pty::<64>();
+    assert_eq!(lora_e5.send_timeout(4, true), SEND_TIMEOUT);
+    modem.write_all(b"+RETRY: 3\r\n").unwrap();
+    lora_e5.set_retries(3).unwrap();
+    assert_eq!(lora_e5.send_timeout(4, true), SEND_TIMEOUT * 4);
+    assert_eq!(lora_e5.send_timeout(4, false), SEND_TIMEOUT);
+
+    // with the data rate known, each attempt gets its airtime plus the receive windows
+    lora_e5.region = Some(Region::Eu868);
+    lora_e5.datarate = Some(DR::_0);
+    let per_attempt = airtime(Region::Eu868, DR::_0, 51).unwrap() + RECEIVE_WINDOWS_TIMEOUT;
+    assert_eq!(lora_e5.send_timeout(51, false), per_attempt);
+    assert_eq!(lora_e5.send_timeout(51, true), per_attempt * 4);
+    assert!(lora_e5.send_timeout(1, false) < lora_e5.send_timeout(51, false));
+}
+
+#[test]
+fn parse_ack() {
     let response = "+CMSGHEX: Start\r
-    +CMSGHEX: Wait ACK\r
-    +CMSGHEX: FPENDING\r
-    +CMSGHEX: ACK Received\r
-    +CMSGHEX: RXWIN1, RSSI -79, SNR 7.0\r
-    +CMSGHEX: Done\r
++CMSGHEX: Wait ACK\r
++CMSGHEX: ACK Received\r
++CMSGHEX: RXWIN2, RSSI -90, SNR 1.5\r
++CMSGHEX: Done\r
 ";
-    if let Some(m) = response.find("RXWIN1") {
-        let (rssi, snr) = parse_rssi_snr(&response, m).unwrap();
-        assert_eq!(rssi, -79);
-        assert_eq!(snr, 7.0);
-    } else {
-        assert!(false)
+    let downlink = parse_downlink(response).unwrap().unwrap();
+    assert!(downlink.ack);
+    assert_eq!(downlink.payload, None);
+
+    let response = "+MSGHEX: PORT: 2; RX: \"01\"\r\n+MSGHEX: RXWIN1, RSSI -54, SNR 9.5\r\n";
+    let downlink = parse_downlink(response).unwrap().unwrap();
+    assert!(!downlink.ack);
+}
+
+#[cfg(all(unix, feature = "sync"))]
+#[test]
+fn sync_worker() {
+    use std::io::Write;
+    let (lora_e5, mut modem) = lora_pty::<64>();
+    let (client, handle) = sync::spawn(lora_e5);
+    modem
+        .write_all(b"+ID: AppEui, 01:23:45:67:89:AB:CD:EF\r\n")
+        .unwrap();
+    let app_eui = client.get_app_eui().unwrap();
+    assert_eq!(app_eui, AppEui::from_str("0123456789ABCDEF").unwrap());
+    client.send_shutdown().unwrap();
+    handle.join().unwrap();
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn credentials_serde_round_trip() {
+    let json = r#"{"app_eui":"6081F9A498856DCC","app_key":"72F36B996179E634537FCA76047D0B51","dev_eui":"60:81:F9:A7:75:27:85:64"}"#;
+    let credentials: Credentials = serde_json::from_str(json).unwrap();
+    assert_eq!(
+        credentials.dev_eui,
+        DevEui::from_str("6081F9A775278564").unwrap()
+    );
+    let serialized = serde_json::to_string(&credentials).unwrap();
+    assert!(serialized.contains(r#""dev_eui":"6081F9A775278564""#));
+    let round_trip: Credentials = serde_json::from_str(&serialized).unwrap();
+    assert_eq!(round_trip.app_key, credentials.app_key);
+    assert_eq!(
+        round_trip.app_key.to_string(),
+        "72F36B996179E634537FCA76047D0B51"
+    );
+    assert!(serde_json::from_str::<AppEui>(r#""0123""#).is_err());
+}
+
+#[test]
+fn port_not_found_message() {
+    let error = Error::PortNotFound {
+        vid: SILICON_LABS_VID,
+        pid: CP210X_UART_BRIDGE_PID,
+    };
+    assert_eq!(
+        error.to_string(),
+        "unable to find port with vid = 0x10c4 and pid = 0xea60"
+    );
+}
+
+#[test]
+fn usb_port_matching() {
+    use serialport::{SerialPortInfo, SerialPortType, UsbPortInfo};
+    let port = |vid, pid, product: &str| SerialPortInfo {
+        port_name: "COM12".to_string(),
+        port_type: SerialPortType::UsbPort(UsbPortInfo {
+            vid,
+            pid,
+            serial_number: None,
+            manufacturer: None,
+            product: Some(product.to_string()),
+        }),
+    };
+    let cp210x = "Silicon Labs CP210x USB to UART Bridge (COM12)";
+    let vid = SILICON_LABS_VID;
+    let pid = CP210X_UART_BRIDGE_PID;
+    assert!(builder::usb_port(port(vid, pid, "CP2102N"), vid, pid, false).is_some());
+    assert!(builder::usb_port(port(0, 0, cp210x), vid, pid, false).is_none());
+    let found = builder::usb_port(port(0, 0, cp210x), vid, pid, true).unwrap();
+    assert_eq!(found.name, "COM12");
+    assert!(builder::usb_port(port(0, 0, "USB Serial Device (COM12)"), vid, pid, true).is_none());
+
+    assert_eq!(builder::device_path("COM12"), r"\\.\COM12");
+    assert_eq!(builder::device_path("com3"), r"\\.\com3");
+    assert_eq!(builder::device_path(r"\\.\COM12"), r"\\.\COM12");
+    assert_eq!(builder::device_path("/dev/ttyUSB0"), "/dev/ttyUSB0");
+}
+
+#[test]
+fn read_multiline_response() {
+    let (mut lora_e5, mock) = lora_mock::<128>();
+    let dump = "+CH: 3; 0,868100000,DR0,DR5; 1,868300000,DR0,DR5; 2,868500000,DR0,DR5\r
++CH: RX1 delay 1000\r
+";
+    mock.expect("AT+CH", dump);
+    lora_e5.write_command("AT+CH").unwrap();
+    let n = lora_e5
+        .read_until_quiet(Duration::from_millis(20), Duration::from_millis(100))
+        .unwrap();
+    assert_eq!(std::str::from_utf8(&lora_e5.buf[..n]).unwrap(), dump);
+    assert!(matches!(
+        lora_e5.read_until_quiet(Duration::from_millis(20), Duration::from_millis(50)),
+        Err(Error::PartialResponse(_))
+    ));
+}
+
+#[test]
+fn read_tolerates_invalid_utf8() {
+    let (mut lora_e5, mock) = lora_mock::<64>();
+    mock.push_read(b"+TEST: \xe2\x82");
+    mock.push_read(b"\xac \xff\r\n");
+    assert_eq!(
+        lora_e5.read_until_break(Duration::from_millis(50)).unwrap(),
+        14
+    );
+
+    mock.push_read(b"+TEST: \xe2\x82");
+    let Err(Error::PartialResponse(partial)) = lora_e5.read_until_break(Duration::from_millis(20))
+    else {
+        panic!("read did not time out")
+    };
+    assert_eq!(partial, "+TEST: \u{fffd}");
+}
+
+#[test]
+fn read_until_byte_pattern() {
+    let (mut lora_e5, mock) = lora_mock::<64>();
+    mock.push_read(b"\x00\xff\x10\xfe\xfe");
+    let n = lora_e5
+        .read_until_bytes(&[b"\r\n", b"\xfe\xfe"], Duration::from_millis(50))
+        .unwrap();
+    assert_eq!(&lora_e5.buf[..n], b"\x00\xff\x10\xfe\xfe");
+}
+
+#[test]
+fn read_unsolicited_output() {
+    let (mut lora_e5, mock) = lora_mock::<128>();
+    assert!(lora_e5.read_unsolicited(Duration::ZERO).unwrap().is_empty());
+    mock.push_read(
+        "+EVT: RX\r
++MSG: PORT: 3; RX: \"0102\"\r
++MSG: RXWIN0, RSSI -40, SNR 9.0\r
++AT: OK\r
+",
+    );
+    let unsolicited = lora_e5
+        .read_unsolicited(Duration::from_millis(100))
+        .unwrap();
+    let [Unsolicited::Line(event), Unsolicited::Downlink(downlink), Unsolicited::Line(banner)] =
+        &unsolicited[..]
+    else {
+        panic!("unexpected {unsolicited:?}")
+    };
+    assert_eq!(event, "+EVT: RX");
+    assert_eq!(downlink.port, Some(3));
+    assert_eq!(downlink.payload, Some(vec![1, 2]));
+    assert_eq!(banner, "+AT: OK");
+    assert_eq!(lora_e5.last_snr, Some(9.0));
+    assert!(mock.is_done());
+}
+
+#[test]
+fn run_script() {
+    let (mut lora_e5, mock) = lora_mock::<128>();
+    mock.expect("AT+DR=EU868", "+DR: EU868\r\n");
+    mock.expect("AT+LW=VER", "+LW: VER, V104\r\n");
+    assert_eq!(
+        lora_e5
+            .run_script(&[("AT+DR=EU868", "+DR: "), ("AT+LW=VER", "+LW: VER, ")])
+            .unwrap(),
+        ["EU868", "V104"]
+    );
+
+    mock.expect("AT+DR=EU868", "+DR: EU868\r\n");
+    mock.expect("AT+CLASS=D", "+CLASS: ERROR(-1)\r\n");
+    let e = lora_e5
+        .run_script(&[
+            ("AT+DR=EU868", "+DR: "),
+            ("AT+CLASS=D", "+CLASS: "),
+            ("AT+LW=VER", "+LW: VER, "),
+        ])
+        .unwrap_err();
+    let Error::Script {
+        index,
+        command,
+        source,
+    } = e
+    else {
+        panic!("unexpected {e:?}")
+    };
+    assert_eq!((index, command.as_str()), (1, "AT+CLASS=D"));
+    assert!(matches!(*source, Error::CommandError { code: -1 }));
+    assert!(mock.is_done());
+
+    mock.expect(
+        "AT+DR=DR3",
+        "+DR: US915 DR3\r\n+DR: US915 DR3 SF7 BW125K\r\n",
+    );
+    mock.expect("AT+LW=VER", "+LW: VER, V104\r\n");
+    mock.expect(
+        "AT+JOIN",
+        "+JOIN: Start\r\n+JOIN: NORMAL\r\n+JOIN: Join failed\r\n+JOIN: Done\r\n",
+    );
+    mock.expect("AT+LW=VER", "+LW: VER, V104\r\n");
+    assert_eq!(
+        lora_e5
+            .run_script(&[
+                ("AT+DR=DR3", "+DR: "),
+                ("AT+LW=VER", "+LW: VER, "),
+                ("AT+JOIN", "+JOIN: "),
+                ("AT+LW=VER", "+LW: VER, "),
+            ])
+            .unwrap(),
+        [
+            "US915 DR3\nUS915 DR3 SF7 BW125K",
+            "V104",
+            "Start\nNORMAL\nJoin failed\nDone",
+            "V104"
+        ]
+    );
+    assert!(mock.is_done());
+}
+
+#[test]
+fn subbands_and_channels() {
+    let (mut lora_e5, mock) = lora_mock::<512>();
+    assert!(matches!(
+        lora_e5.set_subband(9),
+        Err(Error::InvalidSubband(9))
+    ));
+    for ch in 0..72 {
+        let enable = (0..8).contains(&ch) || ch == 64;
+        let state = if enable { "on" } else { "off" };
+        mock.expect(
+            format!("AT+CH={ch},{state}"),
+            format!("+CH: CH{ch} {state}\r\n"),
+        );
+    }
+    lora_e5.set_subband(1).unwrap();
+
+    let dump = (0..8)
+        .chain([64])
+        .map(|ch| format!("{ch},{},DR0,DR3", 902_300_000 + ch * 200_000))
+        .collect::<Vec<_>>()
+        .join("; ");
+    mock.expect("AT+CH", format!("+CH: 9; {dump}\r\n"));
+    let channels = lora_e5.get_channels().unwrap();
+    for (ch, enabled) in channels.iter().enumerate() {
+        assert_eq!(*enabled, ch < 8 || ch == 64, "channel {ch}");
+    }
+    assert!(mock.is_done());
+}
+
+#[test]
+fn channel_plan() {
+    let (mut lora_e5, mock) = lora_mock::<512>();
+    mock.expect(
+        "AT+CH",
+        "+CH: 3; 0,868100000,DR0,DR5; 1,868300000,DR0,DR5; 2,868500000,DR0,DR5\r\n",
+    );
+    let channels = lora_e5.get_channel_plan().unwrap();
+    assert_eq!(channels.len(), 3);
+    assert_eq!(
+        channels[2],
+        Channel {
+            index: 2,
+            freq_hz: 868_500_000,
+            enabled: true,
+            dr_min: DR::_0,
+            dr_max: DR::_5,
+        }
+    );
+
+    lora_e5.region = Some(Region::Us915);
+    mock.expect(
+        "AT+CH",
+        "+CH: 2; 8,903900000,DR0,DR3; 65,904600000,DR4,DR4\r\n",
+    );
+    let channels = lora_e5.get_channel_plan().unwrap();
+    assert_eq!(channels.len(), 72);
+    let enabled: Vec<u8> = channels
+        .iter()
+        .filter(|channel| channel.enabled)
+        .map(|channel| channel.index)
+        .collect();
+    assert_eq!(enabled, [8, 65]);
+    assert_eq!(channels[0].freq_hz, 902_300_000);
+    assert_eq!(channels[63].freq_hz, 914_900_000);
+    assert_eq!((channels[63].dr_min, channels[63].dr_max), (DR::_0, DR::_3));
+    assert_eq!(channels[71].freq_hz, 914_200_000);
+    assert_eq!((channels[71].dr_min, channels[71].dr_max), (DR::_4, DR::_4));
+    assert_eq!(channels[65].dr_min, DR::_4);
+
+    mock.expect("AT+CH", "+CH: 1; 0,868100000\r\n");
+    assert!(matches!(
+        lora_e5.get_channel_plan(),
+        Err(Error::UnexpectedResponse { .. })
+    ));
+    assert!(mock.is_done());
+}
+
+#[test]
+fn define_channel() {
+    let (mut lora_e5, mock) = lora_mock::<128>();
+    mock.expect(
+        "AT+CH=3, 867.1, DR0, DR5",
+        "+CH: 3,867100000,DR0:SF12 BW125KHZ ~ DR5:SF7 BW125KHZ\r\n",
+    );
+    lora_e5
+        .define_channel(3, 867_100_000, DR::_0, DR::_5)
+        .unwrap();
+    mock.expect(
+        "AT+CH=4, 868, DR0, DR5",
+        "+CH: CH4, 868000000, DR0 ~ DR5\r\n",
+    );
+    lora_e5
+        .define_channel(4, 868_000_000, DR::_0, DR::_5)
+        .unwrap();
+    mock.expect(
+        "AT+CH=5, 867.5, DR0, DR5",
+        "+CH: 5,867500000,DR0:SF12 BW125KHZ ~ DR1:SF11 BW125KHZ\r\n",
+    );
+    assert!(matches!(
+        lora_e5.define_channel(5, 867_500_000, DR::_0, DR::_5),
+        Err(Error::UnexpectedResponse { .. })
+    ));
+    assert!(matches!(
+        lora_e5.define_channel(5, 867_500_000, DR::_5, DR::_0),
+        Err(Error::InvalidDatarateRange { .. })
+    ));
+    assert!(mock.is_done());
+}
+
+#[test]
+fn dwell_time() {
+    let (mut lora_e5, mock) = lora_mock::<64>();
+    mock.expect("AT+LW=DWELL, OFF, OFF", "+LW: DWELL, OFF, OFF\r\n");
+    lora_e5.set_dwell_time(false, false).unwrap();
+    mock.expect("AT+LW=DWELL, ON, OFF", "+LW: DWELL, ON, ON\r\n");
+    assert!(lora_e5.set_dwell_time(true, false).is_err());
+    mock.expect("AT+LW=DWELL", "+LW: DWELL, ON, OFF\r\n");
+    assert_eq!(lora_e5.get_dwell_time().unwrap(), (true, false));
+    assert!(mock.is_done());
+}
+
+#[test]
+fn frame_counters() {
+    let (mut lora_e5, mock) = lora_mock::<64>();
+    mock.expect("AT+LW=ULDL", "+LW: ULDL, 12, 3\r\n");
+    assert_eq!(
+        lora_e5.get_frame_counters().unwrap(),
+        FrameCounters {
+            uplink: 12,
+            downlink: 3
+        }
+    );
+    mock.expect("AT+LW=ULDL, 100, 7", "+LW: ULDL, 100, 7\r\n");
+    lora_e5
+        .set_frame_counters(FrameCounters {
+            uplink: 100,
+            downlink: 7,
+        })
+        .unwrap();
+    assert!(mock.is_done());
+    assert!(matches!(
+        lorawan::parse_frame_counters("12\r\n"),
+        Err(Error::FailedToParseFrameCounters(_))
+    ));
+}
+
+#[test]
+fn reconnect_reopens_transport() {
+    let (mut lora_e5, _) = lora_mock::<64>();
+    assert!(matches!(
+        lora_e5.reconnect(),
+        Err(Error::ReconnectUnsupported)
+    ));
+
+    let replacement = MockPort::new();
+    let reopened = replacement.clone();
+    lora_e5.reopen = Some(Box::new(move || Ok(reopened.clone())));
+    // the original port has nothing scripted, so the write fails like a vanished device would
+    assert!(matches!(lora_e5.set_port(2), Err(Error::Io(_))));
+    lora_e5.reconnect().unwrap();
+    replacement.expect("AT+PORT=2", "+PORT: 2\r\n");
+    lora_e5.set_port(2).unwrap();
+    assert!(replacement.is_done());
+}
+
+#[test]
+fn read_errors_are_reported() {
+    let (mut lora_e5, mock) = lora_mock::<64>();
+    mock.expect_read_error("AT+VDD", io::ErrorKind::BrokenPipe);
+    assert!(matches!(lora_e5.get_vdd(), Err(Error::Io(_))));
+    assert!(mock.is_done());
+}
+
+/// A runtime with a one-shot reconnect policy over `lora_e5`, whose port reopens as `replacement`
+#[cfg(feature = "runtime")]
+fn runtime_with_reconnect(
+    mut lora_e5: LoraE5<64, MockPort>,
+    replacement: &MockPort,
+) -> (process::Client, tokio::task::JoinHandle<process::Result>) {
+    let reopened = replacement.clone();
+    lora_e5.reopen = Some(Box::new(move || Ok(reopened.clone())));
+    let setup = process::Setup::default().reconnect(process::ReconnectPolicy {
+        attempts: 1,
+        backoff: Duration::from_millis(1),
+    });
+    let client = setup.get_client();
+    (client, tokio::spawn(setup.complete().run(lora_e5)))
+}
+
+#[cfg(feature = "runtime")]
+#[tokio::test]
+async fn runtime_reconnects_on_read_error() {
+    let (lora_e5, mock) = lora_mock::<64>();
+    let replacement = MockPort::new();
+    mock.expect_read_error("AT+VDD", io::ErrorKind::BrokenPipe);
+    replacement.expect("AT+VDD", "+VDD: 3.30V\r\n");
+    let (client, handle) = runtime_with_reconnect(lora_e5, &replacement);
+    assert_eq!(client.get_vdd().await.unwrap(), 3.3);
+    client.send_shutdown().await.unwrap();
+    handle.await.unwrap().unwrap();
+    assert!(mock.is_done());
+    assert!(replacement.is_done());
+}
+
+#[cfg(feature = "runtime")]
+#[tokio::test]
+async fn runtime_does_not_replay_uplinks() {
+    let (lora_e5, mock) = lora_mock::<64>();
+    let replacement = MockPort::new();
+    mock.expect("AT+PORT=1", "+PORT: 1\r\n");
+    mock.expect_read_error("AT+MSGHEX=\"01\"", io::ErrorKind::BrokenPipe);
+    let (client, handle) = runtime_with_reconnect(lora_e5, &replacement);
+    assert!(matches!(
+        client.send(vec![1], 1, false).await,
+        Err(process::Error::LoraE5(Error::Io(_)))
+    ));
+    client.send_shutdown().await.unwrap();
+    handle.await.unwrap().unwrap();
+    assert!(mock.is_done());
+    assert!(replacement.written().is_empty());
+}
+
+/// Scripts an unconfirmed one byte uplink on `port`, setting the port first if `set_port`
+#[cfg(any(feature = "runtime", feature = "sync"))]
+fn expect_uplink(mock: &MockPort, port: u8, set_port: bool) {
+    if set_port {
+        mock.expect(format!("AT+PORT={port}"), format!("+PORT: {port}\r\n"));
+    }
+    mock.expect("AT+MSGHEX=\"01\"", "+MSGHEX: Start\r\n+MSGHEX: Done\r\n");
+}
+
+#[cfg(feature = "runtime")]
+#[tokio::test]
+async fn runtime_raw_port_command_clears_port_cache() {
+    let (lora_e5, mock) = lora_mock::<64>();
+    expect_uplink(&mock, 1, true);
+    expect_uplink(&mock, 1, false);
+    mock.expect("at+port=5", "+PORT: 5\r\n");
+    expect_uplink(&mock, 1, true);
+    let (client, handle) = process::spawn(lora_e5);
+    client.send(vec![1], 1, false).await.unwrap();
+    client.send(vec![1], 1, false).await.unwrap();
+    client
+        .at_command("at+port=5", Duration::from_millis(100))
+        .await
+        .unwrap();
+    client.send(vec![1], 1, false).await.unwrap();
+    client.send_shutdown().await.unwrap();
+    handle.await.unwrap().unwrap();
+    assert!(mock.is_done());
+}
+
+#[cfg(feature = "sync")]
+#[test]
+fn sync_raw_port_command_clears_port_cache() {
+    let (lora_e5, mock) = lora_mock::<64>();
+    expect_uplink(&mock, 1, true);
+    mock.expect("AT+PORT=5", "+PORT: 5\r\n");
+    expect_uplink(&mock, 1, true);
+    let (client, handle) = sync::spawn(lora_e5);
+    client.send(vec![1], 1, false).unwrap();
+    client
+        .at_command("AT+PORT=5", Duration::from_millis(100))
+        .unwrap();
+    client.send(vec![1], 1, false).unwrap();
+    client.send_shutdown().unwrap();
+    handle.join().unwrap();
+    assert!(mock.is_done());
+}
+
+#[test]
+fn raw_commands_clear_payload_limit() {
+    let (mut lora_e5, mock) = lora_mock::<64>();
+    let timeout = Duration::from_millis(100);
+    for (cmd, prelude, region, datarate) in [
+        ("AT+DR=DR3", "+DR: ", Some(Region::Eu868), None),
+        ("at+dr=3", "+DR: ", Some(Region::Eu868), None),
+        ("AT+ADR=ON", "+ADR: ", Some(Region::Eu868), None),
+        ("AT+DR=US915", "+DR: ", None, None),
+        ("AT+FDEFAULT", "+FDEFAULT: ", None, None),
+        ("AT+LW=VER", "+LW: ", Some(Region::Eu868), Some(DR::_0)),
+    ] {
+        lora_e5.region = Some(Region::Eu868);
+        lora_e5.datarate = Some(DR::_0);
+        mock.expect(cmd, format!("{prelude}OK\r\n"));
+        lora_e5.command(cmd, prelude, timeout).unwrap();
+        assert_eq!(
+            (lora_e5.region, lora_e5.datarate),
+            (region, datarate),
+            "{cmd}"
+        );
+    }
+    assert!(mock.is_done());
+}
+
+#[cfg(feature = "sync")]
+#[test]
+fn sync_serves_runtime_requests() {
+    let (lora_e5, mock) = lora_mock::<64>();
+    mock.expect("AT+VDD", "+VDD: 3.30V\r\n");
+    mock.expect("AT+TEMP", "+TEMP: 24.5\r\n");
+    let (client, handle) = sync::spawn(lora_e5);
+    assert_eq!(client.get_vdd().unwrap(), 3.30);
+    assert_eq!(client.get_temperature().unwrap(), 24.5);
+    client.send_shutdown().unwrap();
+    handle.join().unwrap();
+    assert!(mock.is_done());
+}
+
+#[test]
+fn duty_cycle() {
+    let (mut lora_e5, mock) = lora_mock::<64>();
+    mock.expect("AT+LW=DC, OFF", "+LW: DC, OFF\r\n");
+    lora_e5.set_duty_cycle(false).unwrap();
+    mock.expect("AT+LW=DC", "+LW: DC, ON, 0\r\n");
+    assert!(lora_e5.get_duty_cycle().unwrap());
+    assert!(mock.is_done());
+
+    lora_e5.region = Some(Region::Us915);
+    assert!(matches!(
+        lora_e5.set_duty_cycle(false),
+        Err(Error::DutyCycleUnsupported(Region::Us915))
+    ));
+}
+
+#[test]
+fn provision_otaa() {
+    let credentials = Credentials::new(
+        DevEui::from_str("6081F9A775278564").unwrap(),
+        AppEui::from_str("6081F9A498856DCC").unwrap(),
+        AppKey::from_str("72F36B996179E634537FCA76047D0B51").unwrap(),
+    );
+    let (mut lora_e5, mock) = lora_mock::<128>();
+    mock.expect("AT+MODE=LWOTAA", "+MODE: LWOTAA\r\n");
+    mock.expect("AT+DR=EU868", "+DR: EU868\r\n");
+    mock.expect(
+        "AT+ID=DevEui, 6081F9A775278564",
+        "+ID: DevEui, 60:81:F9:A7:75:27:85:64\r\n",
+    );
+    mock.expect(
+        "AT+ID=AppEui, 6081F9A498856DCC",
+        "+ID: AppEui, 60:81:F9:A4:98:85:6D:CC\r\n",
+    );
+    mock.expect(
+        "AT+KEY=APPKEY, 72F36B996179E634537FCA76047D0B51",
+        "+KEY: APPKEY 72F36B996179E634537FCA76047D0B51\r\n",
+    );
+    mock.expect("AT+DR=5", "+DR: DR5\r\n+DR: EU868 DR5  SF7  BW125K \r\n");
+    mock.expect(
+        "AT+JOIN=FORCE",
+        "+JOIN: Start\r\n+JOIN: Join failed\r\n+JOIN: Done\r\n",
+    );
+    let config = ProvisionConfig::new(Region::Eu868, credentials.clone())
+        .datarate(DR::_5)
+        .force_join(true);
+    assert_eq!(
+        lora_e5.provision(&config).unwrap(),
+        JoinResponse::JoinFailed(JoinFailure::NoAccept)
+    );
+    assert!(mock.is_done());
+
+    mock.expect("AT+MODE=LWOTAA", "+MODE: LWOTAA\r\n");
+    mock.expect("AT+DR=EU868", "+DR: US915\r\n");
+    let error = lora_e5
+        .provision(&ProvisionConfig::new(Region::Eu868, credentials))
+        .unwrap_err();
+    assert!(matches!(
+        error,
+        Error::Provision {
+            step: "set region",
+            ..
+        }
+    ));
+}
+
+#[test]
+fn get_app_key() {
+    let (mut lora_e5, mock) = lora_mock::<64>();
+    mock.expect(
+        "AT+KEY=APPKEY",
+        "+KEY: APPKEY 72F36B996179E634537FCA76047D0B51\r\n",
+    );
+    assert_eq!(
+        lora_e5.get_app_key().unwrap(),
+        AppKey::from_str("72F36B996179E634537FCA76047D0B51").unwrap()
+    );
+    mock.expect(
+        "AT+KEY=APPKEY",
+        "+KEY: APPKEY ********************************\r\n",
+    );
+    assert!(matches!(lora_e5.get_app_key(), Err(Error::KeyMasked)));
+}
+
+#[test]
+fn get_temperature() {
+    let (mut lora_e5, mock) = lora_mock::<64>();
+    mock.expect("AT+TEMP", "+TEMP: 24.5\r\n");
+    assert_eq!(lora_e5.get_temperature().unwrap(), 24.5);
+    mock.expect("AT+TEMP", "+TEMP: ERROR(-1)\r\n");
+    assert!(matches!(
+        lora_e5.get_temperature(),
+        Err(Error::Unsupported("AT+TEMP"))
+    ));
+}
+
+#[test]
+fn send_ascii_downlink() {
+    let (mut lora_e5, mock) = lora_mock::<256>();
+    mock.expect("AT+PORT=2", "+PORT: 2\r\n");
+    mock.expect(
+        "AT+MSG=\"6869\"",
+        "+MSG: Start\r
++MSG: PORT: 2; RX: \"ok \"then\"\"\r
++MSG: RXWIN1, RSSI -50, SNR 8.0\r
++MSG: Done\r
+",
+    );
+    let downlink = lora_e5
+        .send_ascii("hi", 2, false)
+        .unwrap()
+        .outcome
+        .into_downlink()
+        .unwrap();
+    assert_eq!(downlink.port, Some(2));
+    assert_eq!(downlink.payload_str(), Some("ok \"then\""));
+
+    mock.expect(
+        "AT+MSG=\"6869\"",
+        b"+MSG: PORT: 2; RX: \"\xff\x01\"\r\n+MSG: RXWIN2, RSSI -90, SNR 1.0\r\n+MSG: Done\r\n",
+    );
+    let downlink = lora_e5
+        .send_ascii("hi", 2, false)
+        .unwrap()
+        .outcome
+        .into_downlink()
+        .unwrap();
+    assert_eq!(downlink.payload, Some(vec![0xff, 0x01]));
+    assert_eq!(downlink.payload_str(), None);
+    assert!(mock.is_done());
+}
+
+#[test]
+fn probe() {
+    let (mut lora_e5, mock) = lora_mock::<64>();
+    mock.expect("AT", "");
+    mock.expect("AT", "+AT: OK\r\n");
+    lora_e5.probe().unwrap();
+    assert!(mock.is_done());
+
+    for _ in 0..3 {
+        mock.expect("AT", "");
+    }
+    assert!(matches!(
+        lora_e5.probe(),
+        Err(Error::Unresponsive { attempts: 3 })
+    ));
+    assert!(mock.is_done());
+}
+
+#[test]
+fn builder_probes_transport() {
+    let mock = MockPort::new();
+    mock.expect("AT", "+AT: OK\r\n");
+    LoraE5Builder::<64>::new()
+        .probe(true)
+        .with_transport(mock.clone())
+        .unwrap();
+    assert!(mock.is_done());
+
+    for _ in 0..3 {
+        mock.expect("AT", "");
+    }
+    assert!(matches!(
+        LoraE5Builder::<64>::new()
+            .probe(true)
+            .with_transport(mock.clone()),
+        Err(Error::Unresponsive { attempts: 3 })
+    ));
+    assert!(mock.is_done());
+}
+
+#[test]
+fn clear_buffers() {
+    let (mut lora_e5, mock) = lora_mock::<64>();
+    mock.push_read("+MSGHEX: Done\r\n");
+    lora_e5.clear_buffers().unwrap();
+    assert!(mock.is_done());
+    mock.expect("AT+PORT=3", "+PORT: 3\r\n");
+    lora_e5.set_port(3).unwrap();
+    assert!(mock.is_done());
+}
+
+#[test]
+fn drain_stale_output() {
+    let (mut lora_e5, mock) = lora_mock::<64>();
+    let stale = "+MSG: Done\r\n+JOIN: Done\r\n";
+    mock.push_read(stale);
+    assert_eq!(
+        lora_e5.drain(Duration::from_millis(20)).unwrap(),
+        stale.len()
+    );
+    mock.expect("AT", "+AT: OK\r\n");
+    assert!(lora_e5.is_ok().unwrap());
+    assert!(mock.is_done());
+}
+
+#[test]
+fn eu868_region_defaults() {
+    let (mut lora_e5, mock) = lora_mock::<64>();
+    mock.expect("AT+DR=EU868", "+DR: EU868\r\n");
+    for ch in 0..16 {
+        let state = if ch < 3 { "on" } else { "off" };
+        mock.expect(
+            format!("AT+CH={ch},{state}"),
+            format!("+CH: CH{ch} {state}\r\n"),
+        );
+    }
+    lora_e5.apply_region_defaults(Region::Eu868).unwrap();
+    assert!(mock.is_done());
+
+    mock.expect("AT+DR=AS923", "+DR: AS923\r\n");
+    lora_e5.apply_region_defaults(Region::As923).unwrap();
+    assert!(mock.is_done());
+}
+
+#[test]
+fn port_range() {
+    let (mut lora_e5, mock) = lora_mock::<64>();
+    for port in [0, 224] {
+        assert!(matches!(
+            lora_e5.set_port(port),
+            Err(Error::InvalidPort(p)) if p == port
+        ));
+        assert!(matches!(
+            lora_e5.send(&[1], port, false),
+            Err(Error::InvalidPort(_))
+        ));
+        assert!(matches!(
+            lora_e5.send_ascii("a", port, false),
+            Err(Error::InvalidPort(_))
+        ));
+    }
+    assert!(mock.written().is_empty());
+    for port in [1, 223] {
+        mock.expect(format!("AT+PORT={port}"), format!("+PORT: {port}\r\n"));
+        lora_e5.set_port(port).unwrap();
+    }
+    assert!(mock.is_done());
+}
+
+#[test]
+fn payload_size_limit() {
+    assert_eq!(max_payload_size(Region::Us915, DR::_0), Some(11));
+    assert_eq!(max_payload_size(Region::Eu868, DR::_5), Some(222));
+    assert_eq!(max_payload_size(Region::As923, DR::_5), None);
+
+    let (mut lora_e5, mock) = lora_mock::<128>();
+    mock.expect("AT+DR=US915", "+DR: US915\r\n");
+    lora_e5.set_region(Region::Us915).unwrap();
+    mock.expect("AT+DR=0", "+DR: DR0\r\n+DR: US915 DR0  SF10 BW125K \r\n");
+    lora_e5.set_datarate(DR::_0).unwrap();
+    assert!(matches!(
+        lora_e5.send(&[0; 12], 1, false),
+        Err(Error::PayloadTooLarge { len: 12, max: 11 })
+    ));
+    assert!(matches!(
+        lora_e5.send_ascii("six ch", 1, false),
+        Err(Error::PayloadTooLarge { len: 12, max: 11 })
+    ));
+    assert!(mock.is_done());
+
+    // EU868 DR0 carries up to 51 bytes, which text sent as hex reaches at 25 characters
+    mock.expect("AT+DR=EU868", "+DR: EU868\r\n");
+    lora_e5.set_region(Region::Eu868).unwrap();
+    mock.expect("AT+DR=0", "+DR: DR0\r\n+DR: EU868 DR0  SF12 BW125K \r\n");
+    lora_e5.set_datarate(DR::_0).unwrap();
+    let text = "a".repeat(26);
+    assert!(matches!(
+        lora_e5.send_ascii(&text, 1, false),
+        Err(Error::PayloadTooLarge { len: 52, max: 51 })
+    ));
+    mock.expect("AT+PORT=1", "+PORT: 1\r\n");
+    mock.expect(
+        format!("AT+MSG=\"{}\"", hex::encode(&text[..25])),
+        "+MSG: Start\r\n+MSG: Done\r\n",
+    );
+    let report = lora_e5.send_ascii(&text[..25], 1, false).unwrap();
+    assert_eq!(report.airtime, airtime(Region::Eu868, DR::_0, 50));
+    assert!(mock.is_done());
+}
+
+#[test]
+fn close_sleeps_when_configured() {
+    let mock = MockPort::new();
+    let lora_e5 = LoraE5Builder::<64>::new()
+        .with_transport(mock.clone())
+        .unwrap();
+    lora_e5.close().unwrap();
+    assert!(mock.written().is_empty());
+
+    mock.expect("AT+LOWPOWER", "+LOWPOWER: SLEEP\r\n");
+    let lora_e5 = LoraE5Builder::<64>::new()
+        .sleep_on_close(true)
+        .with_transport(mock.clone())
+        .unwrap();
+    lora_e5.close().unwrap();
+    assert!(mock.is_done());
+
+    mock.expect("AT+LOWPOWER", "+LOWPOWER: SLEEP\r\n");
+    drop(
+        LoraE5Builder::<64>::new()
+            .sleep_on_close(true)
+            .with_transport(mock.clone())
+            .unwrap(),
+    );
+    assert!(mock.is_done());
+}
+
+#[test]
+fn set_and_get_class() {
+    let (mut lora_e5, mock) = lora_mock::<64>();
+    mock.expect("AT+CLASS=C", "+CLASS: C\r\n");
+    lora_e5.set_class(Class::C).unwrap();
+    mock.expect("AT+CLASS", "+CLASS: C\r\n");
+    assert_eq!(lora_e5.get_class().unwrap(), Class::C);
+    mock.expect("AT+CLASS=B", "+CLASS: A\r\n");
+    assert!(matches!(
+        lora_e5.set_class(Class::B),
+        Err(Error::UnexpectedResponse { .. })
+    ));
+    assert!(mock.is_done());
+}
+
+#[test]
+fn unexpected_response_names_expected_and_received() {
+    let (mut lora_e5, mock) = lora_mock::<64>();
+    mock.expect("AT+ADR=ON", "+AT: OK\r\n");
+    let e = lora_e5.set_adr(true).unwrap_err();
+    assert_eq!(
+        e.to_string(),
+        "unexpected at response: expected '+ADR: ', got '+AT: OK'"
+    );
+    mock.expect("AT+CLASS=C", "+CLASS: A\r\n");
+    let e = lora_e5.set_class(Class::C).unwrap_err();
+    assert_eq!(
+        e.to_string(),
+        "unexpected at response: expected '+CLASS: C', got '+CLASS: A'"
+    );
+    assert!(mock.is_done());
+}
+
+#[test]
+fn query_retries_timed_out_read() {
+    let mock = MockPort::new();
+    let mut lora_e5 = LoraE5Builder::<64>::new()
+        .default_command_timeout(Duration::from_millis(50))
+        .query_attempts(2)
+        .with_transport(mock.clone())
+        .unwrap();
+    mock.expect("AT+VER", "");
+    mock.expect("AT+VER", "+VER: 4.0.11\r\n");
+    assert_eq!(lora_e5.get_version().unwrap(), "4.0.11");
+    assert!(mock.is_done());
+
+    let mock = MockPort::new();
+    let mut lora_e5 = LoraE5Builder::<64>::new()
+        .default_command_timeout(Duration::from_millis(50))
+        .with_transport(mock.clone())
+        .unwrap();
+    mock.expect("AT+VER", "");
+    assert!(matches!(
+        lora_e5.get_version(),
+        Err(Error::PartialResponse(_))
+    ));
+}
+
+#[test]
+fn parse_hex_with_separators() {
+    let dev_eui = DevEui::from_str("6081F9A775278564").unwrap();
+    for input in [
+        "60 81 F9 A7 75 27 85 64",
+        "0x6081F9A775278564",
+        "0x60 0x81 0xF9 0xA7 0x75 0x27 0x85 0x64",
+        "60-81-F9-A7-75-27-85-64",
+        "60:81:f9:a7:75:27:85:64",
+        " 6081F9A775278564\n",
+    ] {
+        assert_eq!(DevEui::from_str(input).unwrap(), dev_eui, "{input:?}");
+    }
+    assert!(matches!(
+        DevEui::from_str("60 81 F9"),
+        Err(ParseError::VecWrongSize(3))
+    ));
+}
+
+#[test]
+fn credentials_from_bytes() {
+    let bytes = [0x60, 0x81, 0xF9, 0xA7, 0x75, 0x27, 0x85, 0x64];
+    let dev_eui = DevEui::try_from(&bytes[..]).unwrap();
+    assert_eq!(dev_eui, DevEui::from_str("6081F9A775278564").unwrap());
+    assert_eq!(dev_eui.as_bytes(), &bytes);
+    assert_eq!(dev_eui.as_ref(), &bytes[..]);
+    assert!(matches!(
+        DevAddr::try_from(&bytes[..]),
+        Err(ParseError::VecWrongSize(8))
+    ));
+}
+
+#[test]
+fn eui_byte_order() {
+    let dev_eui = DevEui::from_str("6081F9A775278564").unwrap();
+    assert_eq!(
+        DevEui::from_str_reversed("64 85 27 75 A7 F9 81 60").unwrap(),
+        dev_eui
+    );
+    assert_eq!(dev_eui.to_string_reversed(), "64852775A7F98160");
+    assert_eq!(dev_eui.reversed().reversed(), dev_eui);
+    let app_eui = AppEui::from_str_reversed("CC6D8598A4F98160").unwrap();
+    assert_eq!(app_eui.to_string(), "6081F9A498856DCC");
+}
+
+#[test]
+fn cached_ids() {
+    let mock = MockPort::new();
+    let mut lora_e5 = LoraE5Builder::<128>::new()
+        .cache_ids(true)
+        .with_transport(mock.clone())
+        .unwrap();
+    let dev_eui = DevEui::from_str("6081F9A775278564").unwrap();
+    mock.expect("AT+ID=DevEui", "+ID: DevEui, 60:81:F9:A7:75:27:85:64\r\n");
+    assert_eq!(lora_e5.get_dev_eui().unwrap(), dev_eui);
+    assert_eq!(lora_e5.get_dev_eui().unwrap(), dev_eui);
+
+    let app_eui = AppEui::from_str("6081F9A498856DCC").unwrap();
+    mock.expect(
+        "AT+ID=AppEui, 6081F9A498856DCC",
+        "+ID: AppEui, 60:81:F9:A4:98:85:6D:CC\r\n",
+    );
+    lora_e5.set_app_eui(&app_eui).unwrap();
+    assert_eq!(lora_e5.get_app_eui().unwrap(), app_eui);
+    assert!(mock.is_done());
+
+    lora_e5.refresh_ids();
+    mock.expect("AT+ID=DevEui", "+ID: DevEui, 60:81:F9:A7:75:27:85:64\r\n");
+    assert_eq!(lora_e5.get_dev_eui().unwrap(), dev_eui);
+    assert!(mock.is_done());
+
+    let (mut lora_e5, mock) = lora_mock::<128>();
+    for _ in 0..2 {
+        mock.expect("AT+ID=DevEui", "+ID: DevEui, 60:81:F9:A7:75:27:85:64\r\n");
+        assert_eq!(lora_e5.get_dev_eui().unwrap(), dev_eui);
+    }
+    assert!(mock.is_done());
+}
+
+#[test]
+fn set_datarate_learns_region_from_echo() {
+    let (mut lora_e5, mock) = lora_mock::<128>();
+    mock.expect("AT+DR=6", "+DR: DR6\r\n+DR: EU868 DR6  SF7  BW250K \r\n");
+    lora_e5.set_datarate(DR::_6).unwrap();
+    assert_eq!(lora_e5.region, Some(Region::Eu868));
+    mock.expect("AT+DR=3", "+DR: DR3\r\n+DR: US915 DR3  SF7  BW125K \r\n");
+    let e = lora_e5.set_datarate(DR::_3).unwrap_err();
+    assert_eq!(
+        e.to_string(),
+        "unexpected at response: expected '+DR: EU868 DR3', got '+DR: US915 DR3  SF7  BW125K'"
+    );
+    assert!(mock.is_done());
+}
+
+#[test]
+fn get_datarate() {
+    let (mut lora_e5, mock) = lora_mock::<128>();
+    mock.expect("AT+DR", "+DR: DR3\r\n+DR: US915 DR3  SF7  BW125K \r\n");
+    assert_eq!(lora_e5.get_datarate().unwrap(), DR::_3);
+    assert!(mock.is_done());
+}
+
+#[test]
+fn key_mismatch_is_redacted() {
+    let (mut lora_e5, mock) = lora_mock::<64>();
+    let app_key = AppKey::from_str("72F36B996179E634537FCA76047D0B51").unwrap();
+    mock.expect(
+        "AT+KEY=APPKEY, 72F36B996179E634537FCA76047D0B51",
+        "+KEY: APPKEY 2B7E151628AED2A6ABF7158809CF4F3C\r\n",
+    );
+    let e = lora_e5.set_app_key(&app_key).unwrap_err();
+    assert!(matches!(e, Error::UnexpectedResponse { .. }));
+    let message = e.to_string();
+    assert!(!message.contains("72F36B99"), "{message}");
+    assert!(!message.contains("2B7E1516"), "{message}");
+
+    let nwk_skey = NwkSKey::from_str("72F36B996179E634537FCA76047D0B51").unwrap();
+    mock.expect(
+        "AT+KEY=NWKSKEY, 72F36B996179E634537FCA76047D0B51",
+        "+KEY: NWKSKEY 2B7E151628AED2A6ABF7158809CF4F3C\r\n",
+    );
+    let message = lora_e5.set_nwkskey(&nwk_skey).unwrap_err().to_string();
+    assert!(!message.contains("72F36B99") && !message.contains("2B7E1516"));
+
+    let app_skey = AppSKey::from_str("72F36B996179E634537FCA76047D0B51").unwrap();
+    mock.expect(
+        "AT+KEY=APPSKEY, 72F36B996179E634537FCA76047D0B51",
+        "+KEY: APPSKEY 2B7E151628AED2A6ABF7158809CF4F3C\r\n",
+    );
+    let message = lora_e5.set_appskey(&app_skey).unwrap_err().to_string();
+    assert!(!message.contains("72F36B99") && !message.contains("2B7E1516"));
+    assert!(mock.is_done());
+}
+
+#[test]
+fn keys_are_redacted_in_commands_and_echoes() {
+    assert_eq!(
+        redact_keys("AT+KEY=APPKEY, 72F36B996179E634537FCA76047D0B51\n"),
+        "AT+KEY=APPKEY, ****\n"
+    );
+    assert_eq!(
+        redact_keys(
+            "+ID: DevEui, 60:81:F9:A7\r\n+KEY: NWKSKEY 72F36B996179E634537FCA76047D0B51\r\n"
+        ),
+        "+ID: DevEui, 60:81:F9:A7\r\n+KEY: NWKSKEY ****\r\n"
+    );
+    assert_eq!(
+        redact_keys("+DR: 72F36B996179E634537FCA76047D0B51"),
+        "+DR: 72F36B996179E634537FCA76047D0B51"
+    );
+
+    let (mut lora_e5, mock) = lora_mock::<64>();
+    mock.expect(
+        "AT+KEY=APPKEY, 72F36B996179E634537FCA76047D0B51",
+        "+KEY: APPKEY 72F36B996179E634537FCA76047D0B51\r\n",
+    );
+    let message = lora_e5
+        .run_script(&[(
+            "AT+KEY=APPKEY, 72F36B996179E634537FCA76047D0B51",
+            "+KEY: APPSKEY ",
+        )])
+        .unwrap_err()
+        .to_string();
+    assert!(!message.contains("72F36B99"), "{message}");
+    assert!(mock.is_done());
+}
+
+#[test]
+fn keys_are_redacted_in_debug() {
+    let app_key = AppKey::from_str("72F36B996179E634537FCA76047D0B51").unwrap();
+    assert_eq!(format!("{app_key:?}"), "AppKey(****…0B51)");
+    assert_eq!(app_key.to_string(), "72F36B996179E634537FCA76047D0B51");
+    let credentials = Credentials::new(
+        DevEui::from_str("6081F9A775278564").unwrap(),
+        AppEui::from_str("6081F9A498856DCC").unwrap(),
+        app_key,
+    );
+    assert!(!format!("{credentials:?}").contains("72F3"));
+    let dev_eui = DevEui::from_str("6081F9A775278564").unwrap();
+    assert_eq!(
+        format!("{dev_eui:?}"),
+        "DevEui([96, 129, 249, 167, 117, 39, 133, 100])"
+    );
+}
+
+#[test]
+fn send_outcomes() {
+    let (mut lora_e5, mock) = lora_mock::<256>();
+    mock.expect("AT+PORT=1", "+PORT: 1\r\n");
+    mock.expect("AT+MSGHEX=\"01\"", "+MSGHEX: Start\r\n+MSGHEX: Done\r\n");
+    assert!(matches!(
+        lora_e5.send(&[1], 1, false).unwrap().outcome,
+        SendOutcome::Sent
+    ));
+
+    mock.expect(
+        "AT+CMSGHEX=\"01\"",
+        "+CMSGHEX: Start\r\n+CMSGHEX: Wait ACK\r\n+CMSGHEX: Done\r\n",
+    );
+    assert!(matches!(
+        lora_e5.send(&[1], 1, true).unwrap().outcome,
+        SendOutcome::NoAck
+    ));
+
+    mock.expect(
+        "AT+CMSGHEX=\"01\"",
+        "+CMSGHEX: Start\r\n+CMSGHEX: Wait ACK\r\n+CMSGHEX: ACK Received\r\n+CMSGHEX: Done\r\n",
+    );
+    assert!(matches!(
+        lora_e5.send(&[1], 1, true).unwrap().outcome,
+        SendOutcome::Acked(None)
+    ));
+
+    mock.expect(
+        "AT+MSGHEX=\"01\"",
+        "+MSGHEX: Start\r\n+MSGHEX: PORT: 2; RX: \"AB\"\r\n+MSGHEX: RXWIN1, RSSI -60, SNR 5.0\r\n+MSGHEX: Done\r\n",
+    );
+    let outcome = lora_e5.send(&[1], 1, false).unwrap().outcome;
+    assert!(matches!(outcome, SendOutcome::Downlink(_)));
+    assert_eq!(outcome.downlink().unwrap().payload, Some(vec![0xAB]));
+    assert!(mock.is_done());
+}
+
+#[test]
+fn send_skips_unchanged_port() {
+    let (mut lora_e5, mock) = lora_mock::<128>();
+    mock.expect("AT+PORT=1", "+PORT: 1\r\n");
+    mock.expect("AT+MSGHEX=\"01\"", "+MSGHEX: Start\r\n+MSGHEX: Done\r\n");
+    mock.expect("AT+MSGHEX=\"02\"", "+MSGHEX: Start\r\n+MSGHEX: Done\r\n");
+    lora_e5.send(&[1], 1, false).unwrap();
+    lora_e5.send(&[2], 1, false).unwrap();
+
+    mock.expect("AT+PORT=2", "+PORT: 2\r\n");
+    mock.expect("AT+MSG=\"6869\"", "+MSG: Start\r\n+MSG: Done\r\n");
+    lora_e5.send_ascii("hi", 2, false).unwrap();
+
+    mock.expect("AT+FDEFAULT", "+FDEFAULT: OK\r\n");
+    lora_e5.factory_reset().unwrap();
+    mock.expect("AT+PORT=2", "+PORT: 2\r\n");
+    mock.expect("AT+MSGHEX=\"03\"", "+MSGHEX: Start\r\n+MSGHEX: Done\r\n");
+    lora_e5.send(&[3], 2, false).unwrap();
+    assert!(mock.is_done());
+}
+
+#[test]
+fn send_raw_leaves_port_alone() {
+    let (mut lora_e5, mock) = lora_mock::<128>();
+    mock.expect("AT+PORT=7", "+PORT: 7\r\n");
+    lora_e5.set_port(7).unwrap();
+    mock.expect("AT+MSGHEX=\"01\"", "+MSGHEX: Start\r\n+MSGHEX: Done\r\n");
+    mock.expect(
+        "AT+CMSGHEX=\"02\"",
+        "+CMSGHEX: Start\r\n+CMSGHEX: Wait ACK\r\n+CMSGHEX: ACK Received\r\n+CMSGHEX: Done\r\n",
+    );
+    assert!(matches!(
+        lora_e5.send_raw(&[1], false).unwrap().outcome,
+        SendOutcome::Sent
+    ));
+    assert!(matches!(
+        lora_e5.send_raw(&[2], true).unwrap().outcome,
+        SendOutcome::Acked(None)
+    ));
+    assert!(mock.is_done());
+}
+
+#[test]
+fn datarate_stepping() {
+    assert!(DR::_0 < DR::_1);
+    assert!(DR::_9 < DR::_10);
+    assert_eq!(DR::ALL.iter().max(), Some(&DR::_15));
+    assert_eq!(DR::_3.increment(), Some(DR::_4));
+    assert_eq!(DR::_3.decrement(), Some(DR::_2));
+    assert_eq!(DR::_14.increment(), Some(DR::_15));
+    assert_eq!(DR::_15.increment(), None);
+    assert_eq!(DR::_1.decrement(), Some(DR::_0));
+    assert_eq!(DR::_0.decrement(), None);
+}
+
+#[test]
+fn send_await_downlink() {
+    let (mut lora_e5, mock) = lora_mock::<256>();
+    mock.expect("AT+PORT=1", "+PORT: 1\r\n");
+    mock.expect(
+        "AT+MSGHEX=\"01\"",
+        "+MSGHEX: Start\r
++MSGHEX: PORT: 3; RX: \"AA\"\r
++MSGHEX: RXWIN1, RSSI -60, SNR 5.0\r
++MSGHEX: Done\r
++MSG: PORT: 2; RX: \"BB\"\r
++MSG: RXWIN0, RSSI -40, SNR 9.0\r
+",
+    );
+    let downlink = lora_e5
+        .send_await_downlink(&[1], 1, Some(2), Duration::from_millis(500))
+        .unwrap();
+    assert_eq!(downlink.port, Some(2));
+    assert_eq!(downlink.payload, Some(vec![0xBB]));
+
+    mock.expect(
+        "AT+MSGHEX=\"02\"",
+        "+MSGHEX: Start\r\n+MSGHEX: PORT: 3; RX: \"AA\"\r\n+MSGHEX: RXWIN1, RSSI -60, SNR 5.0\r\n+MSGHEX: Done\r\n",
+    );
+    let downlink = lora_e5
+        .send_await_downlink(&[2], 1, None, Duration::from_millis(50))
+        .unwrap();
+    assert_eq!(downlink.port, Some(3));
+
+    mock.expect("AT+MSGHEX=\"03\"", "+MSGHEX: Start\r\n+MSGHEX: Done\r\n");
+    assert!(matches!(
+        lora_e5.send_await_downlink(&[3], 1, Some(2), Duration::from_millis(50)),
+        Err(Error::NoDownlink)
+    ));
+    assert!(mock.is_done());
+}
+
+#[test]
+fn command_delay_spaces_out_commands() {
+    let mock = MockPort::new();
+    let mut lora_e5 = LoraE5Builder::<64>::new()
+        .command_delay(Duration::from_millis(20))
+        .with_transport(mock.clone())
+        .unwrap();
+    mock.expect("AT+PORT=1", "+PORT: 1\r\n");
+    mock.expect("AT+PORT=2", "+PORT: 2\r\n");
+    let start = std::time::Instant::now();
+    lora_e5.set_port(1).unwrap();
+    lora_e5.set_port(2).unwrap();
+    assert!(start.elapsed() >= Duration::from_millis(40));
+    assert!(mock.is_done());
+}
+
+#[test]
+fn line_ending() {
+    let mock = MockPort::new();
+    let mut lora_e5 = LoraE5::<64, _>::with_transport(mock.clone());
+    mock.expect("AT+PORT=1", "+PORT: 1\r\n");
+    lora_e5.set_port(1).unwrap();
+    assert_eq!(mock.written(), b"AT+PORT=1\n");
+
+    let mock = MockPort::new();
+    let mut lora_e5 = LoraE5Builder::<64>::new()
+        .line_ending("\r\n")
+        .with_transport(mock.clone())
+        .unwrap();
+    mock.expect("AT+PORT=1", "+PORT: 1\r\n");
+    lora_e5.set_port(1).unwrap();
+    assert_eq!(mock.written(), b"AT+PORT=1\r\n");
+}
+
+#[test]
+fn parse_text_and_hex_downlinks() {
+    let downlink = parse_downlink(
+        "+MSGHEX: Start\r
++MSGHEX: PORT: 8; RX: \"6869\"\r
++MSGHEX: RXWIN2, RSSI -101, SNR -3.5\r
++MSGHEX: Done\r
+",
+    )
+    .unwrap()
+    .unwrap();
+    assert_eq!(downlink.port, Some(8));
+    assert_eq!(downlink.payload, Some(b"hi".to_vec()));
+    assert_eq!(downlink.rssi, -101);
+    assert_eq!(downlink.snr, -3.5);
+    assert!(!downlink.ack);
+
+    let downlink = parse_ascii_downlink(
+        b"+CMSG: Start\r
++CMSG: Wait ACK\r
++CMSG: ACK Received\r
++CMSG: PORT: 8; RX: \"6869\"\r
++CMSG: RXWIN1, RSSI -101, SNR -3.5\r
++CMSG: Done\r
+",
+    )
+    .unwrap()
+    .unwrap();
+    assert_eq!(downlink.port, Some(8));
+    assert_eq!(downlink.payload_str(), Some("6869"));
+    assert_eq!(downlink.rssi, -101);
+    assert_eq!(downlink.snr, -3.5);
+    assert!(downlink.ack);
+}
+
+#[test]
+fn busy_and_not_joined_replies() {
+    let (mut lora_e5, mock) = lora_mock::<128>();
+    mock.expect("AT+PORT=1", "+PORT: 1\r\n");
+    mock.expect(
+        "AT+CMSGHEX=\"01\"",
+        "+CMSGHEX: Please join network first\r\n",
+    );
+    assert!(matches!(lora_e5.send(&[1], 1, true), Err(Error::NotJoined)));
+    mock.expect("AT+MSG=\"6869\"", "+MSG: Please join network first\r\n");
+    assert!(matches!(
+        lora_e5.send_ascii("hi", 1, false),
+        Err(Error::NotJoined)
+    ));
+    mock.expect(
+        "AT+MSGHEX=\"01\"",
+        "+MSGHEX: LoRaWAN modem is busy\r\n+MSGHEX: Done\r\n",
+    );
+    assert!(matches!(lora_e5.send(&[1], 1, false), Err(Error::Busy)));
+    mock.expect("AT+ADR=ON", "+ADR: LoRaWAN modem is busy\r\n");
+    assert!(matches!(lora_e5.set_adr(true), Err(Error::Busy)));
+    assert!(mock.is_done());
+}
+
+#[test]
+fn join_with_timeout() {
+    let (mut lora_e5, mock) = lora_mock::<128>();
+    let timeout = Duration::from_secs(60);
+    mock.expect(
+        "AT+JOIN=FORCE",
+        "+JOIN: Start\r\n+JOIN: Join failed\r\n+JOIN: Done\r\n",
+    );
+    assert_eq!(
+        lora_e5.join_with_timeout(true, timeout).unwrap(),
+        JoinResponse::JoinFailed(JoinFailure::NoAccept)
+    );
+    assert!(!lora_e5.joined_this_session());
+    mock.expect("AT+JOIN", "+JOIN: Joined already\r\n");
+    assert_eq!(
+        lora_e5.join_with_timeout(false, timeout).unwrap(),
+        JoinResponse::AlreadyJoined
+    );
+    assert!(lora_e5.joined_this_session());
+    assert!(mock.is_done());
+}
+
+#[test]
+fn join_failure_reasons() {
+    let (mut lora_e5, mock) = lora_mock::<128>();
+    mock.expect("AT+JOIN", "+JOIN: LoRaWAN modem is busy\r\n");
+    assert_eq!(
+        lora_e5.join().unwrap(),
+        JoinResponse::JoinFailed(JoinFailure::Busy)
+    );
+    mock.expect("AT+JOIN=FORCE", "+JOIN: Start\r\n+JOIN: NORMAL\r\n");
+    assert_eq!(
+        lora_e5
+            .join_with_timeout(true, Duration::from_millis(50))
+            .unwrap(),
+        JoinResponse::JoinFailed(JoinFailure::Timeout)
+    );
+    assert!(!lora_e5.joined_this_session());
+    assert!(mock.is_done());
+}
+
+#[test]
+fn join_with_retries() {
+    let (mut lora_e5, mock) = lora_mock::<128>();
+    let backoff = Duration::from_millis(10);
+    for _ in 0..2 {
+        mock.expect(
+            "AT+JOIN=FORCE",
+            "+JOIN: Start\r\n+JOIN: Join failed\r\n+JOIN: Done\r\n",
+        );
     }
+    mock.expect(
+        "AT+JOIN=FORCE",
+        "+JOIN: Start\r\n+JOIN: Network joined\r\n+JOIN: Done\r\n",
+    );
+    assert!(matches!(
+        lora_e5.join_with_retries(true, 3, backoff).unwrap(),
+        JoinResponse::JoinComplete(_)
+    ));
+    assert!(mock.is_done());
+
+    mock.expect("AT+JOIN", "+JOIN: LoRaWAN modem is busy\r\n");
+    mock.expect(
+        "AT+JOIN",
+        "+JOIN: Start\r\n+JOIN: Join failed\r\n+JOIN: Done\r\n",
+    );
+    assert_eq!(
+        lora_e5.join_with_retries(false, 2, backoff).unwrap(),
+        JoinResponse::JoinFailed(JoinFailure::NoAccept)
+    );
+    assert!(mock.is_done());
+}
+
+#[test]
+fn require_joined_guards_send() {
+    let mock = MockPort::new();
+    let mut lora_e5 = LoraE5Builder::<128>::new()
+        .require_joined(true)
+        .with_transport(mock.clone())
+        .unwrap();
+    assert!(matches!(
+        lora_e5.send(&[1], 1, false),
+        Err(Error::NotJoined)
+    ));
+    assert!(mock.written().is_empty());
+
+    mock.expect(
+        "AT+JOIN",
+        "+JOIN: Start\r\n+JOIN: Network joined\r\n+JOIN: Done\r\n",
+    );
+    lora_e5.join().unwrap();
+    mock.expect("AT+PORT=1", "+PORT: 1\r\n");
+    mock.expect("AT+MSGHEX=\"01\"", "+MSGHEX: Start\r\n+MSGHEX: Done\r\n");
+    assert!(matches!(
+        lora_e5.send(&[1], 1, false).unwrap().outcome,
+        SendOutcome::Sent
+    ));
+    assert!(mock.is_done());
+}
+
+#[test]
+fn custom_command() {
+    let (mut lora_e5, mock) = lora_mock::<64>();
+    let timeout = Duration::from_millis(100);
+    mock.expect("AT+LW=VER", "+LW: VER, V104\r\n");
+    assert_eq!(
+        lora_e5.command("AT+LW=VER", "+LW: VER, ", timeout).unwrap(),
+        "V104"
+    );
+    mock.expect("AT+LW=VER", "+LW: ERROR(-10)\r\n");
+    assert!(matches!(
+        lora_e5.command("AT+LW=VER", "+LW: VER, ", timeout),
+        Err(Error::CommandError { code: -10 })
+    ));
+    assert!(mock.is_done());
+}
+
+#[test]
+fn uplink_airtime() {
+    let ms = |region, dr, len| airtime(region, dr, len).unwrap().as_secs_f64() * 1000.0;
+    assert!((ms(Region::Eu868, DR::_5, 0) - 46.3).abs() < 0.1);
+    assert!((ms(Region::Eu868, DR::_0, 0) - 1155.1).abs() < 0.1);
+    assert!((ms(Region::Us915, DR::_0, 11) - 370.7).abs() < 0.1);
+    assert_eq!(airtime(Region::Eu868, DR::_7, 10), None);
+
+    let (mut lora_e5, mock) = lora_mock::<128>();
+    mock.expect("AT+PORT=1", "+PORT: 1\r\n");
+    mock.expect("AT+MSGHEX=\"01\"", "+MSGHEX: Start\r\n+MSGHEX: Done\r\n");
+    assert_eq!(lora_e5.send(&[1], 1, false).unwrap().airtime, None);
+    mock.expect("AT+DR=5", "+DR: DR5\r\n+DR: EU868 DR5  SF7  BW125K \r\n");
+    lora_e5.set_datarate(DR::_5).unwrap();
+    mock.expect("AT+MSGHEX=\"01\"", "+MSGHEX: Start\r\n+MSGHEX: Done\r\n");
+    let report = lora_e5.send(&[1], 1, false).unwrap();
+    assert_eq!(report.airtime, airtime(Region::Eu868, DR::_5, 1));
+    assert!(mock.is_done());
+}
+
+#[test]
+fn modem_status() {
+    let (mut lora_e5, mock) = lora_mock::<128>();
+    mock.expect("AT+VER", "+VER: 4.0.11\r\n");
+    mock.expect("AT+DR=SCHEME", "+DR: US915\r\n");
+    mock.expect("AT+MODE", "+MODE: LWOTAA\r\n");
+    mock.expect("AT+CLASS", "+CLASS: A\r\n");
+    mock.expect("AT+DR", "+DR: DR2\r\n+DR: US915 DR2  SF8  BW125K \r\n");
+    mock.expect("AT+ADR", "+ADR: ON\r\n");
+    mock.expect("AT+ID=DevEui", "+ID: DevEui, 60:81:F9:A7:75:27:85:64\r\n");
+    mock.expect("AT+ID=AppEui", "+ID: AppEui, 60:81:F9:A4:98:85:6D:CC\r\n");
+    mock.expect("AT+ID=DevAddr", "+ID: ERROR(-1)\r\n");
+    let status = lora_e5.status().unwrap();
+    assert_eq!(status.version, Some(Version::new(4, 0, 11)));
+    assert_eq!(status.region, Some(Region::Us915));
+    assert_eq!(status.mode, Some(Mode::Otaa));
+    assert_eq!(status.class, Some(Class::A));
+    assert_eq!(status.datarate, Some(DR::_2));
+    assert_eq!(status.adr, Some(true));
+    assert!(!status.joined_this_session);
+    assert_eq!(
+        status.dev_eui,
+        Some(DevEui::from_str("6081F9A775278564").unwrap())
+    );
+    assert!(status.app_eui.is_some());
+    assert_eq!(status.dev_addr, None);
+    assert!(mock.is_done());
 }
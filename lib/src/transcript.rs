@@ -0,0 +1,80 @@
+use super::*;
+
+/// One line of the modem's report of an uplink, such as `+CMSGHEX: Wait ACK`
+#[derive(Debug, Clone, PartialEq)]
+pub enum SendEvent {
+    /// The modem started the transmission
+    Start,
+    /// A confirmed uplink was sent and the modem is waiting for its acknowledgement
+    WaitAck,
+    /// The downlink had FPending set: the network has more downlinks queued, which it sends
+    /// after the next uplink
+    FramePending,
+    AckReceived,
+    /// A downlink was received in receive window 1 or 2
+    RxWindow {
+        window: u8,
+        rssi: isize,
+        snr: f32,
+    },
+    /// The modem finished the uplink and its receive windows
+    Done,
+    /// Any other line, such as a received payload or a link check answer, without its prefix
+    Other(String),
+}
+
+/// Every line the modem printed while sending an uplink, in order
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SendTranscript {
+    pub events: Vec<SendEvent>,
+}
+
+impl SendTranscript {
+    /// Whether the network has more downlinks queued for the device
+    pub fn frame_pending(&self) -> bool {
+        self.events.contains(&SendEvent::FramePending)
+    }
+
+    /// Parses the lines printed for `AT+MSG`, `AT+MSGHEX`, `AT+CMSG` or `AT+CMSGHEX`, such as
+    ///   +CMSGHEX: Start
+    ///   +CMSGHEX: Wait ACK
+    ///   +CMSGHEX: FPENDING
+    ///   +CMSGHEX: ACK Received
+    ///   +CMSGHEX: RXWIN1, RSSI -79, SNR 7.0
+    ///   +CMSGHEX: Done
+    pub(crate) fn parse(response: &str) -> Result<Self> {
+        let mut events = Vec::new();
+        for line in response.lines() {
+            let line = line.trim_end();
+            let Some((_, event)) = line.split_once(": ") else {
+                continue;
+            };
+            events.push(match event {
+                "Start" => SendEvent::Start,
+                "Wait ACK" => SendEvent::WaitAck,
+                FPENDING => SendEvent::FramePending,
+                ACK_RECEIVED => SendEvent::AckReceived,
+                "Done" => SendEvent::Done,
+                _ => match parse_rx_window(event) {
+                    Some(window) => {
+                        let (rssi, snr) = parse_rssi_snr(event, 0)?;
+                        SendEvent::RxWindow { window, rssi, snr }
+                    }
+                    None => SendEvent::Other(event.to_string()),
+                },
+            });
+        }
+        Ok(Self { events })
+    }
+}
+
+/// The window number of a line such as `RXWIN1, RSSI -79, SNR 7.0`
+fn parse_rx_window(event: &str) -> Option<u8> {
+    event
+        .strip_prefix("RXWIN")?
+        .split(',')
+        .next()?
+        .trim()
+        .parse()
+        .ok()
+}
@@ -0,0 +1,100 @@
+use std::io;
+
+/// The byte stream a `LoraE5` talks over.
+///
+/// `read` should return promptly, with `Ok(0)` or an error such as `TimedOut` when no bytes are
+/// available, since overall command timeouts are enforced by the caller.
+pub trait Transport {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize>;
+
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize>;
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Changes the local baud rate, for transports where that is configurable
+    fn set_baud_rate(&mut self, _baud: u32) -> io::Result<()> {
+        Err(io::ErrorKind::Unsupported.into())
+    }
+
+    /// Discards whatever is waiting in the input and output buffers below the transport, such as
+    /// an OS serial driver's. Transports without such buffers needn't do anything.
+    fn clear(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(feature = "serialport")]
+impl Transport for Box<dyn serialport::SerialPort> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        io::Read::read(self, buf)
+    }
+
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        io::Write::write(self, buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        io::Write::flush(self)
+    }
+
+    fn set_baud_rate(&mut self, baud: u32) -> io::Result<()> {
+        serialport::SerialPort::set_baud_rate(self.as_mut(), baud)?;
+        Ok(())
+    }
+
+    fn clear(&mut self) -> io::Result<()> {
+        serialport::SerialPort::clear(self.as_ref(), serialport::ClearBuffer::All)?;
+        Ok(())
+    }
+}
+
+/// Placeholder default transport when the `serialport` feature is disabled. It has no values, so
+/// a `LoraE5` must name its transport explicitly.
+#[cfg(not(feature = "serialport"))]
+#[derive(Debug)]
+pub enum NoTransport {}
+
+#[cfg(not(feature = "serialport"))]
+impl Transport for NoTransport {
+    fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+        match *self {}
+    }
+
+    fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+        match *self {}
+    }
+}
+
+/// Adapts an [`embedded-io`](https://docs.rs/embedded-io) UART, such as a HAL serial peripheral,
+/// into a `Transport`. Reads only take bytes that are already available, so the UART must
+/// implement `ReadReady`.
+#[cfg(feature = "embedded-io")]
+pub struct EmbeddedIo<T>(pub T);
+
+#[cfg(feature = "embedded-io")]
+fn embedded_io_error<E: embedded_io::Error>(e: E) -> io::Error {
+    io::Error::other(format!("{:?}", e.kind()))
+}
+
+#[cfg(feature = "embedded-io")]
+impl<T> Transport for EmbeddedIo<T>
+where
+    T: embedded_io::Read + embedded_io::ReadReady + embedded_io::Write,
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() || !self.0.read_ready().map_err(embedded_io_error)? {
+            return Ok(0);
+        }
+        self.0.read(buf).map_err(embedded_io_error)
+    }
+
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.write(buf).map_err(embedded_io_error)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush().map_err(embedded_io_error)
+    }
+}
@@ -1,3 +1,7 @@
+use super::Error;
+use std::{fmt, str::FromStr, time::Duration};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Mode {
     Test,
     Otaa,
@@ -14,10 +18,117 @@ impl Mode {
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+impl FromStr for Mode {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_uppercase().as_str() {
+            "TEST" => Ok(Mode::Test),
+            "LWABP" => Ok(Mode::Abp),
+            "LWOTAA" => Ok(Mode::Otaa),
+            _ => Err(Error::InvalidModeStr(s.to_string())),
+        }
+    }
+}
+
+/// LoRaWAN device class. Class B needs the modem synchronised to network beacons, which this crate
+/// doesn't manage yet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Class {
+    A,
+    B,
+    C,
+}
+
+impl Class {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Class::A => "A",
+            Class::B => "B",
+            Class::C => "C",
+        }
+    }
+}
+
+impl FromStr for Class {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_uppercase().as_str() {
+            "A" => Ok(Class::A),
+            "B" => Ok(Class::B),
+            "C" => Ok(Class::C),
+            _ => Err(Error::InvalidClassStr(s.to_string())),
+        }
+    }
+}
+
+/// Firmware version as reported by `AT+VER`, such as `4.0.11`. Versions order by their numeric
+/// parts, so features can be gated with `version >= Version::new(4, 0, 11)`.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Version {
+    pub major: u8,
+    pub minor: u8,
+    pub patch: u8,
+    /// Any suffix after the numeric parts, such as `beta` in `1.0.0-beta`
+    pub variant: Option<String>,
+}
+
+impl Version {
+    pub fn new(major: u8, minor: u8, patch: u8) -> Self {
+        Self {
+            major,
+            minor,
+            patch,
+            variant: None,
+        }
+    }
+}
+
+impl FromStr for Version {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || Error::InvalidVersionStr(s.to_string());
+        let s = s.trim();
+        let (numbers, variant) = match s.find(['-', ' ']) {
+            Some(i) => (&s[..i], Some(s[i + 1..].trim().to_string())),
+            None => (s, None),
+        };
+        let mut parts = numbers.split('.').map(|part| part.parse::<u8>());
+        let mut next = || parts.next().and_then(|part| part.ok()).ok_or_else(invalid);
+        let version = Version {
+            major: next()?,
+            minor: next()?,
+            patch: next()?,
+            variant: variant.filter(|variant| !variant.is_empty()),
+        };
+        if parts.next().is_some() {
+            return Err(invalid());
+        }
+        Ok(version)
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)?;
+        if let Some(variant) = &self.variant {
+            write!(f, "-{variant}")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Region {
     Eu868,
     Us915,
+    Au915,
+    As923,
+    Kr920,
+    In865,
+    Ru864,
 }
 
 impl Region {
@@ -25,38 +136,101 @@ impl Region {
         match self {
             Region::Eu868 => "EU868",
             Region::Us915 => "US915",
+            Region::Au915 => "AU915",
+            Region::As923 => "AS923",
+            Region::Kr920 => "KR920",
+            Region::In865 => "IN865",
+            Region::Ru864 => "RU864",
+        }
+    }
+}
+
+impl FromStr for Region {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_uppercase().as_str() {
+            "EU868" => Ok(Region::Eu868),
+            "US915" => Ok(Region::Us915),
+            "AU915" => Ok(Region::Au915),
+            "AS923" => Ok(Region::As923),
+            "KR920" => Ok(Region::Kr920),
+            "IN865" => Ok(Region::In865),
+            "RU864" => Ok(Region::Ru864),
+            _ => Err(Error::InvalidRegionStr(s.to_string())),
         }
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+/// Data rate index. Indexes are ordered numerically, which within a region's uplink data rates
+/// means shorter airtime for higher indexes; see `sf_bw` for what an index means in each region.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub enum DR {
     _0,
     _1,
     _2,
     _3,
     _4,
+    _5,
+    _6,
+    _7,
+    _8,
+    _9,
+    _10,
+    _11,
+    _12,
+    _13,
+    _14,
+    _15,
 }
 
-use super::Error;
-use std::str::FromStr;
-
 impl FromStr for DR {
     type Err = Error;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s {
+        let index = s.strip_prefix("DR").unwrap_or(s);
+        match index {
             "0" => Ok(DR::_0),
             "1" => Ok(DR::_1),
             "2" => Ok(DR::_2),
             "3" => Ok(DR::_3),
             "4" => Ok(DR::_4),
+            "5" => Ok(DR::_5),
+            "6" => Ok(DR::_6),
+            "7" => Ok(DR::_7),
+            "8" => Ok(DR::_8),
+            "9" => Ok(DR::_9),
+            "10" => Ok(DR::_10),
+            "11" => Ok(DR::_11),
+            "12" => Ok(DR::_12),
+            "13" => Ok(DR::_13),
+            "14" => Ok(DR::_14),
+            "15" => Ok(DR::_15),
             _ => Err(Error::InvalidDatarateStr(s.to_string())),
         }
     }
 }
 
 impl DR {
+    pub const ALL: [DR; 16] = [
+        DR::_0,
+        DR::_1,
+        DR::_2,
+        DR::_3,
+        DR::_4,
+        DR::_5,
+        DR::_6,
+        DR::_7,
+        DR::_8,
+        DR::_9,
+        DR::_10,
+        DR::_11,
+        DR::_12,
+        DR::_13,
+        DR::_14,
+        DR::_15,
+    ];
+
     pub fn as_str(&self) -> &str {
         match self {
             DR::_0 => "0",
@@ -64,26 +238,142 @@ impl DR {
             DR::_2 => "2",
             DR::_3 => "3",
             DR::_4 => "4",
+            DR::_5 => "5",
+            DR::_6 => "6",
+            DR::_7 => "7",
+            DR::_8 => "8",
+            DR::_9 => "9",
+            DR::_10 => "10",
+            DR::_11 => "11",
+            DR::_12 => "12",
+            DR::_13 => "13",
+            DR::_14 => "14",
+            DR::_15 => "15",
         }
     }
 
-    pub fn termination_pattern(&self) -> &str {
-        match self {
-            DR::_0 => "US915 DR0  SF10 BW125K \r\n",
-            DR::_1 => "US915 DR1  SF9  BW125K \r\n",
-            DR::_2 => "US915 DR2  SF8  BW125K \r\n",
-            DR::_3 => "US915 DR3  SF7  BW125K \r\n",
-            DR::_4 => "US915 DR4  SF8  BW500K \r\n",
+    /// The next higher data rate index, or `None` for DR15. Not every index is valid in every
+    /// region, so check the result with `sf_bw` when stepping across a region's range.
+    pub fn increment(&self) -> Option<DR> {
+        DR::ALL.get(*self as usize + 1).copied()
+    }
+
+    /// The next lower data rate index, or `None` for DR0
+    pub fn decrement(&self) -> Option<DR> {
+        (*self as usize).checked_sub(1).map(|index| DR::ALL[index])
+    }
+
+    /// Returns the LoRa spreading factor and bandwidth (in kHz) of this data rate in the given
+    /// region, or `None` if the data rate is FSK or reserved (RFU) in that region.
+    pub fn sf_bw(&self, region: Region) -> Option<(u8, u16)> {
+        match region {
+            Region::Us915 => match self {
+                DR::_0 => Some((10, 125)),
+                DR::_1 => Some((9, 125)),
+                DR::_2 => Some((8, 125)),
+                DR::_3 => Some((7, 125)),
+                DR::_4 => Some((8, 500)),
+                DR::_8 => Some((12, 500)),
+                DR::_9 => Some((11, 500)),
+                DR::_10 => Some((10, 500)),
+                DR::_11 => Some((9, 500)),
+                DR::_12 => Some((8, 500)),
+                DR::_13 => Some((7, 500)),
+                _ => None,
+            },
+            Region::Au915 => match self {
+                DR::_0 => Some((12, 125)),
+                DR::_1 => Some((11, 125)),
+                DR::_2 => Some((10, 125)),
+                DR::_3 => Some((9, 125)),
+                DR::_4 => Some((8, 125)),
+                DR::_5 => Some((7, 125)),
+                DR::_6 => Some((8, 500)),
+                DR::_8 => Some((12, 500)),
+                DR::_9 => Some((11, 500)),
+                DR::_10 => Some((10, 500)),
+                DR::_11 => Some((9, 500)),
+                DR::_12 => Some((8, 500)),
+                DR::_13 => Some((7, 500)),
+                _ => None,
+            },
+            Region::Eu868 | Region::As923 | Region::Ru864 | Region::Kr920 | Region::In865 => {
+                match self {
+                    DR::_0 => Some((12, 125)),
+                    DR::_1 => Some((11, 125)),
+                    DR::_2 => Some((10, 125)),
+                    DR::_3 => Some((9, 125)),
+                    DR::_4 => Some((8, 125)),
+                    DR::_5 => Some((7, 125)),
+                    DR::_6 if matches!(region, Region::Eu868 | Region::As923 | Region::Ru864) => {
+                        Some((7, 250))
+                    }
+                    _ => None,
+                }
+            }
         }
     }
 
-    pub fn all_patterns() -> [&'static str; 5] {
-        [
-            DR::_0.termination_pattern(),
-            DR::_1.termination_pattern(),
-            DR::_2.termination_pattern(),
-            DR::_3.termination_pattern(),
-            DR::_4.termination_pattern(),
-        ]
+    /// The line the modem echoes after `AT+DR=<dr>`, eg: "US915 DR0  SF10 BW125K \r\n"
+    pub fn termination_pattern(&self, region: Region) -> Option<String> {
+        let (sf, bw) = self.sf_bw(region)?;
+        Some(format!(
+            "{} DR{:<2} SF{sf:<2} BW{bw}K \r\n",
+            region.as_str(),
+            self.as_str()
+        ))
     }
+
+    pub fn all_patterns(region: Region) -> Vec<String> {
+        DR::ALL
+            .iter()
+            .filter_map(|dr| dr.termination_pattern(region))
+            .collect()
+    }
+}
+
+/// Returns the largest application payload, in bytes, an uplink at `dr` can carry in `region`
+/// (assuming no MAC commands piggyback in FOpts), or `None` where no table is known or the data
+/// rate isn't used for uplinks. Tables are from the LoRaWAN Regional Parameters for US915 and
+/// EU868.
+pub fn max_payload_size(region: Region, dr: DR) -> Option<usize> {
+    match region {
+        Region::Us915 => match dr {
+            DR::_0 => Some(11),
+            DR::_1 => Some(53),
+            DR::_2 => Some(125),
+            DR::_3 | DR::_4 => Some(242),
+            _ => None,
+        },
+        Region::Eu868 => match dr {
+            DR::_0 | DR::_1 | DR::_2 => Some(51),
+            DR::_3 => Some(115),
+            DR::_4 | DR::_5 | DR::_6 | DR::_7 => Some(222),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Estimates the time on air of an uplink carrying `payload_len` bytes of application data at `dr`
+/// in `region`, including the 13 bytes of LoRaWAN framing. Uses the LoRa time on air formula with
+/// the settings LoRaWAN uplinks use: an 8 symbol preamble, explicit header, CRC and coding rate
+/// 4/5. Returns `None` for FSK or reserved data rates.
+pub fn airtime(region: Region, dr: DR, payload_len: usize) -> Option<Duration> {
+    const PREAMBLE_SYMBOLS: f64 = 8.0;
+    const LORAWAN_OVERHEAD: usize = 13;
+    // coding rate 4/5
+    const CODING_RATE: f64 = 1.0;
+    let (sf, bw_khz) = dr.sf_bw(region)?;
+    let sf = f64::from(sf);
+    let symbol = 2f64.powf(sf) / (f64::from(bw_khz) * 1000.0);
+    // low data rate optimization is on whenever a symbol lasts 16ms or more
+    let low_dr_optimize = if symbol >= 0.016 { 1.0 } else { 0.0 };
+    let bits = 8.0 * (payload_len + LORAWAN_OVERHEAD) as f64 - 4.0 * sf + 28.0 + 16.0;
+    let blocks = (bits / (4.0 * (sf - 2.0 * low_dr_optimize)))
+        .ceil()
+        .max(0.0);
+    let payload_symbols = 8.0 + blocks * (CODING_RATE + 4.0);
+    let seconds = (PREAMBLE_SYMBOLS + 4.25 + payload_symbols) * symbol;
+    Some(Duration::from_secs_f64(seconds))
 }
@@ -0,0 +1,91 @@
+use super::*;
+
+/// Something the modem printed without being asked
+#[derive(Debug, Clone)]
+pub enum Unsolicited {
+    /// A downlink received outside of an uplink's receive windows, such as by a Class C device
+    Downlink(Downlink),
+    /// Any other line, such as the boot banner after a reset or a `+EVT:` line, with its line
+    /// ending removed
+    Line(String),
+}
+
+/// Prefixes of the lines the modem prints about a downlink
+const DOWNLINK_PREFIXES: [&str; 4] = ["+MSG: ", "+MSGHEX: ", "+CMSG: ", "+CMSGHEX: "];
+
+impl<const N: usize, T: Transport> LoraE5<N, T> {
+    /// Waits up to `timeout` for output the modem prints unprompted, then reads until it has been
+    /// quiet briefly. With a zero timeout the port is checked once, so this can clear stray output
+    /// before a command without waiting for any. Downlinks are parsed as in `poll_downlink`;
+    /// every other line is returned as is.
+    pub fn read_unsolicited(&mut self, timeout: Duration) -> Result<Vec<Unsolicited>> {
+        let start = time::Instant::now();
+        let mut time = start;
+        let mut received = Vec::new();
+        loop {
+            match self.port.read(&mut self.buf) {
+                Ok(0) => std::thread::sleep(READ_IDLE_SLEEP),
+                Ok(n) => {
+                    trace!(
+                        "read {:?}",
+                        redact_keys(&String::from_utf8_lossy(&self.buf[..n]))
+                    );
+                    received.extend_from_slice(&self.buf[..n]);
+                    time = time::Instant::now();
+                }
+                Err(e) if is_idle_read(&e) => (),
+                Err(e) => return Err(e.into()),
+            }
+            if received.is_empty() {
+                if start.elapsed() >= timeout {
+                    return Ok(Vec::new());
+                }
+            } else if time.elapsed() >= DRAIN_QUIET {
+                break;
+            }
+        }
+        debug!(
+            "<- unsolicited {:?}",
+            redact_keys(&String::from_utf8_lossy(&received))
+        );
+
+        let received = String::from_utf8_lossy(&received);
+        let mut unsolicited = Vec::new();
+        let mut downlink_lines = Vec::new();
+        for line in received.lines() {
+            let line = line.trim_end();
+            if line.is_empty() {
+                continue;
+            }
+            if !DOWNLINK_PREFIXES
+                .iter()
+                .any(|prefix| line.starts_with(prefix))
+            {
+                unsolicited.push(Unsolicited::Line(line.to_string()));
+                continue;
+            }
+            downlink_lines.push(line);
+            // the RSSI line ends the modem's report of a downlink
+            if line.contains("RSSI") {
+                match parse_downlink(&downlink_lines.join("\r\n")) {
+                    Ok(Some(downlink)) => {
+                        self.last_snr = Some(downlink.snr);
+                        unsolicited.push(Unsolicited::Downlink(downlink));
+                    }
+                    _ => unsolicited.extend(
+                        downlink_lines
+                            .iter()
+                            .map(|line| Unsolicited::Line(line.to_string())),
+                    ),
+                }
+                downlink_lines.clear();
+            }
+        }
+        unsolicited.extend(
+            downlink_lines
+                .into_iter()
+                .map(|line| Unsolicited::Line(line.to_string())),
+        );
+        Ok(unsolicited)
+    }
+}